@@ -1,13 +1,23 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::net::SocketAddr;
 use std::time::Duration;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about = "A versatile network diagnostic tool to replace system ping/ping6.", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// List of IP addresses, Domains, or URLs to ping.
-    #[arg(required_unless_present = "fetch_geo")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// List of IP addresses, Domains, or URLs to ping, `-` to read them from stdin.
+    #[arg(required_unless_present_any = ["fetch_geo", "file"])]
     pub targets: Vec<String>,
 
+    /// Read targets from a TOML file, where each target can set its own options,
+    /// or from a plain list with one target per line.
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    pub file: Option<String>,
+
     /// Stop after sending N packets.
     #[arg(short = 'c', overrides_with = "count")]
     pub count: Option<u64>,
@@ -36,6 +46,22 @@ pub struct Cli {
     #[arg(short = 'q')]
     pub quiet: bool,
 
+    /// Probe every address a target resolves to, each with its own statistics, instead of the fastest one.
+    #[arg(long = "all-addrs")]
+    pub all_addrs: bool,
+
+    /// Resolve host names again every N seconds, or when their DNS records expire if later, and follow address changes. TTLs come from the first nameserver in /etc/resolv.conf; names it doesn't answer for are resolved every N seconds.
+    #[arg(long = "re-resolve", value_name = "SECONDS", value_parser = parse_duration, conflicts_with = "all_addrs")]
+    pub re_resolve: Option<Duration>,
+
+    /// Sweep mode, like fping -g: probe every target a few times, then list those alive and those unreachable.
+    #[arg(long = "sweep")]
+    pub sweep: bool,
+
+    /// Send at most N probes per second across all targets. Sweeps default to 100.
+    #[arg(long = "rate", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub rate: Option<u32>,
+
     // Mode Flags (Mutually Exclusive via 'mode' group)
     /// Force IPv4 ICMP ping.
     #[arg(short = '4', group = "mode")]
@@ -53,10 +79,120 @@ pub struct Cli {
     #[arg(short = 'H', long = "http", group = "mode")]
     pub http: bool,
 
+    /// Probe IPv4 neighbors on the local link with ARP requests.
+    #[arg(long = "arp", group = "mode")]
+    pub arp: bool,
+
+    /// Probe IPv6 neighbors on the local link with neighbor solicitations.
+    #[arg(long = "ndp", group = "mode")]
+    pub ndp: bool,
+
+    /// Interface for --arp and --ndp probes. Defaults to the interface on the target's subnet.
+    #[arg(short = 'I', long = "interface", value_name = "IFACE")]
+    pub interface: Option<String>,
+
     /// Custom HTTP headers (e.g., "Host: example.com"). Can be specified multiple times.
     #[arg(long = "header")]
     pub headers: Vec<String>,
 
+    /// Capture a response header (e.g. "x-cache") and report its values. Can be specified multiple times.
+    #[arg(long = "capture-header", value_name = "NAME")]
+    pub capture_headers: Vec<String>,
+
+    /// HTTP request method. Defaults to HEAD, or GET/POST when a body is checked or sent.
+    #[arg(short = 'X', long = "method", value_name = "METHOD")]
+    pub method: Option<String>,
+
+    /// HTTP request body.
+    #[arg(
+        short = 'd',
+        long = "data",
+        value_name = "DATA",
+        conflicts_with = "data_file"
+    )]
+    pub data: Option<String>,
+
+    /// Read the HTTP request body from a file.
+    #[arg(long = "data-file", value_name = "FILE")]
+    pub data_file: Option<String>,
+
+    /// Download the HTTP response body with GET and report the throughput in Mbit/s.
+    #[arg(long = "download")]
+    pub download: bool,
+
+    /// Stop each download after this many bytes (K, M and G suffixes are accepted).
+    #[arg(
+        long = "download-limit",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        requires = "download"
+    )]
+    pub download_limit: Option<usize>,
+
+    /// Probe with HTTP/2 (ALPN over TLS, prior knowledge over plain HTTP).
+    #[arg(long = "http2", conflicts_with = "http3")]
+    pub http2: bool,
+
+    /// Probe with HTTP/3 over QUIC (https:// URLs only).
+    #[arg(long = "http3")]
+    pub http3: bool,
+
+    /// Keep HTTP connections alive and reuse them between probes (default).
+    #[arg(long = "reuse", overrides_with = "no_reuse")]
+    pub reuse: bool,
+
+    /// Open a fresh HTTP connection for every probe to measure cold-connection latency.
+    #[arg(long = "no-reuse", overrides_with = "reuse")]
+    pub no_reuse: bool,
+
+    /// Follow HTTP redirects and report every hop of the chain.
+    #[arg(short = 'L', long = "follow-redirects")]
+    pub follow_redirects: bool,
+
+    /// Maximum number of redirects to follow with -L.
+    #[arg(long = "max-redirects", default_value = "10", value_name = "HOPS")]
+    pub max_redirects: usize,
+
+    /// Probe TCP and HTTP targets through a proxy (http://host:port or socks5://host:port).
+    #[arg(long = "proxy", value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Allow insecure HTTPS connections by skipping certificate verification.
+    #[arg(short = 'k', long = "insecure")]
+    pub insecure: bool,
+
+    /// CA certificate bundle (PEM) used to verify HTTPS servers instead of the system roots.
+    #[arg(long = "cacert", value_name = "FILE")]
+    pub cacert: Option<String>,
+
+    /// Client certificate (PEM) for mutual TLS.
+    #[arg(long = "cert", value_name = "FILE")]
+    pub cert: Option<String>,
+
+    /// Client private key (PEM) for mutual TLS. Defaults to the certificate file.
+    #[arg(long = "key", value_name = "FILE")]
+    pub key: Option<String>,
+
+    /// Accepted HTTP status codes (e.g., "200,204" or "200-299").
+    #[arg(long = "expect-status", value_name = "CODES")]
+    pub expect_status: Option<String>,
+
+    /// Require the HTTP response body to contain this text.
+    #[arg(long = "expect-body", value_name = "TEXT")]
+    pub expect_body: Option<String>,
+
+    /// Require the HTTP response body to match this regular expression.
+    #[arg(long = "expect-body-regex", value_name = "REGEX")]
+    pub expect_body_regex: Option<String>,
+
+    /// Minimum HTTP response body size in bytes.
+    #[arg(long = "min-body-size", value_name = "BYTES")]
+    pub min_body_size: Option<usize>,
+
+    /// Maximum HTTP response body size in bytes.
+    #[arg(long = "max-body-size", value_name = "BYTES")]
+    pub max_body_size: Option<usize>,
+
     /// GeoIP lookup mode. Displays location information for the targets and exits.
     #[arg(short = 'g', long = "geo", group = "mode")]
     pub geo: bool,
@@ -75,9 +211,86 @@ fn parse_duration(arg: &str) -> Result<Duration, std::num::ParseFloatError> {
     Ok(Duration::from_secs_f64(seconds))
 }
 
+/// Parses a byte count with an optional binary K, M or G suffix, e.g. "10M".
+fn parse_size(arg: &str) -> Result<usize, String> {
+    let arg = arg.trim();
+    let (digits, unit) = match arg.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&arg[..i], c.to_ascii_uppercase()),
+        _ => (arg, 'B'),
+    };
+    let shift = match unit {
+        'B' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => return Err(format!("invalid size unit '{}'", unit)),
+    };
+    let value: usize = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", arg))?;
+    value
+        .checked_mul(1 << shift)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("invalid size '{}'", arg))
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run a UDP reflector that answers `pingx://` probes.
+    Serve {
+        /// Address and port to listen on.
+        #[arg(short = 'l', long = "listen", default_value = "[::]:8862")]
+        listen: SocketAddr,
+    },
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Protocol {
     Icmp,
     Tcp(u16),
     Http(String),
+    WebSocket(String),
+    Grpc(String),
+    Dns(String),
+    Udp(u16),
+    Ntp(u16),
+    /// STUN Binding Requests to a server port.
+    Stun(u16),
+    /// Probes answered by a `pingx serve` reflector.
+    Reflector(u16),
+    /// Layer 2 probes on the local link: ARP for IPv4, NDP for IPv6.
+    Arp,
+    Ndp,
+    /// Application protocol probes, with the target URL.
+    Redis(String),
+    Memcached(String),
+    Postgres(String),
+    /// Connections to a Unix domain socket, with its path.
+    Unix(String),
+}
+
+impl Protocol {
+    /// Whether probes may need the TLS settings of `--cacert`, `--cert` and
+    /// `--key`.
+    pub fn uses_tls(&self) -> bool {
+        matches!(
+            self,
+            Self::Http(_) | Self::WebSocket(_) | Self::Grpc(_) | Self::Postgres(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4k"), Ok(4096));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("1.5M").is_err());
+        assert!(parse_size("2T").is_err());
+    }
 }
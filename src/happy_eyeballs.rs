@@ -141,7 +141,7 @@ async fn probe_address(addr: IpAddr, protocol: &Protocol) -> Result<()> {
     match protocol {
         Protocol::Icmp => probe_icmp(addr).await,
         Protocol::Tcp(port) => probe_tcp(addr, *port).await,
-        Protocol::Http(url_str) => {
+        Protocol::Http(url_str) | Protocol::WebSocket(url_str) => {
             let port = if let Ok(url) = reqwest::Url::parse(url_str) {
                 url.port_or_known_default().unwrap_or(80)
             } else {
@@ -149,6 +149,44 @@ async fn probe_address(addr: IpAddr, protocol: &Protocol) -> Result<()> {
             };
             probe_tcp(addr, port).await
         }
+        Protocol::Grpc(url_str) => {
+            let port = match reqwest::Url::parse(url_str) {
+                Ok(url) => url
+                    .port()
+                    .unwrap_or(if url.scheme() == "grpcs" { 443 } else { 80 }),
+                Err(_) => 80,
+            };
+            probe_tcp(addr, port).await
+        }
+        // UDP has no handshake; an address is usable when it can be routed
+        Protocol::Udp(port)
+        | Protocol::Ntp(port)
+        | Protocol::Stun(port)
+        | Protocol::Reflector(port) => crate::pinger::udp::connect(SocketAddr::new(addr, *port))
+            .await
+            .map(|_| ())
+            .context("No route"),
+        Protocol::Redis(url_str) | Protocol::Memcached(url_str) | Protocol::Postgres(url_str) => {
+            let port = reqwest::Url::parse(url_str)
+                .map(|url| crate::pinger::service::port(&url))
+                .unwrap_or_default();
+            probe_tcp(addr, port).await
+        }
+        // Only neighbors on the local link answer; checked when the pinger
+        // looks up the interface
+        Protocol::Arp | Protocol::Ndp | Protocol::Unix(_) => Ok(()),
+        Protocol::Dns(url_str) => {
+            let port = reqwest::Url::parse(url_str)
+                .ok()
+                .and_then(|url| url.port())
+                .unwrap_or(crate::pinger::dns::DEFAULT_PORT);
+            tokio::time::timeout(
+                PROBE_TIMEOUT,
+                crate::pinger::dns::probe_server(SocketAddr::new(addr, port)),
+            )
+            .await
+            .context("Timeout")?
+        }
     }
 }
 
@@ -3,7 +3,10 @@ mod config;
 mod geoip;
 mod happy_eyeballs;
 mod pinger;
+mod reflector;
+mod resolver;
 mod session;
+mod targets;
 mod utils;
 
 use clap::{CommandFactory, Parser};
@@ -19,6 +22,14 @@ async fn main() {
 
     let args = Cli::parse();
 
+    if let Some(cli::Command::Serve { listen }) = args.command {
+        if let Err(e) = reflector::serve(listen).await {
+            eprintln!("pingx: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // GeoIP Fetch
     if args.fetch_geo {
         let mut geo_manager = match geoip::GeoIpManager::new() {
@@ -97,12 +108,20 @@ async fn main() {
         return;
     }
 
-    if let Err(e) = utils::check_and_acquire_privileges(&args).await {
+    let targets = match targets::from_cli(&args) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("pingx: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = utils::check_and_acquire_privileges(&args, &targets).await {
         eprintln!("pingx: {}", e);
         std::process::exit(1);
     }
 
-    let session = Session::new(args);
+    let session = Session::new(args, targets);
 
     if let Err(e) = session.run().await {
         eprintln!("pingx: {}", e);
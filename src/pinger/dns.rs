@@ -0,0 +1,463 @@
+use crate::pinger::Pinger;
+use crate::pinger::udp;
+use crate::session::{DnsDetail, PingResult, ProbeDetail, ProbeStatus};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use reqwest::Url;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc};
+
+pub const DEFAULT_PORT: u16 = 53;
+const MAX_UDP_MESSAGE: usize = 4096;
+
+const RECORD_TYPES: &[(&str, u16)] = &[
+    ("A", 1),
+    ("NS", 2),
+    ("CNAME", 5),
+    ("SOA", 6),
+    ("PTR", 12),
+    ("MX", 15),
+    ("TXT", 16),
+    ("AAAA", 28),
+    ("SRV", 33),
+    ("DS", 43),
+    ("DNSKEY", 48),
+    ("SVCB", 64),
+    ("HTTPS", 65),
+    ("ANY", 255),
+    ("CAA", 257),
+];
+
+/// A question asked to the resolver of a `dns://resolver[:port]/name?type=T`
+/// target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+}
+
+impl DnsQuestion {
+    /// Reads the question from the target URL. The type defaults to A, and
+    /// an empty path asks about the root zone.
+    pub fn from_url(url: &Url) -> Result<Self> {
+        let name = url.path().trim_matches('/');
+        let name = if name.is_empty() { "." } else { name };
+        let qtype = match url.query_pairs().find(|(key, _)| key == "type") {
+            Some((_, value)) => parse_record_type(&value)?,
+            None => 1,
+        };
+        Ok(Self {
+            name: name.to_string(),
+            qtype,
+        })
+    }
+}
+
+/// Parses a record type name such as `AAAA`, or a numeric `TYPE65`.
+pub fn parse_record_type(name: &str) -> Result<u16> {
+    let upper = name.to_ascii_uppercase();
+    RECORD_TYPES
+        .iter()
+        .find(|(n, _)| *n == upper)
+        .map(|(_, code)| *code)
+        .or_else(|| upper.strip_prefix("TYPE")?.parse().ok())
+        .ok_or_else(|| anyhow!("Unknown DNS record type '{}'", name))
+}
+
+fn rcode_label(rcode: u8) -> &'static str {
+    match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        _ => "RCODE?",
+    }
+}
+
+/// Encodes a recursive query for `question` with the given message ID.
+fn encode_query(id: u16, question: &DnsQuestion) -> Result<Vec<u8>> {
+    // ID, flags with RD set, one question
+    let mut msg = Vec::with_capacity(32 + question.name.len());
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in question.name.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            if question.name.trim_end_matches('.').is_empty() {
+                break;
+            }
+            return Err(anyhow!("Invalid DNS name '{}'", question.name));
+        }
+        if label.len() > 63 {
+            return Err(anyhow!("DNS label too long in '{}'", question.name));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    if msg.len() - 12 > 255 {
+        return Err(anyhow!("DNS name too long: '{}'", question.name));
+    }
+
+    msg.extend_from_slice(&question.qtype.to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes());
+    Ok(msg)
+}
+
+/// The parts of a DNS response a probe reports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DnsResponse {
+    rcode: u8,
+    answers: u16,
+    truncated: bool,
+}
+
+/// Reads the header of a response to the query with message ID `id`.
+/// Returns `None` for other messages, e.g. late answers to earlier probes.
+fn parse_response(msg: &[u8], id: u16) -> Option<DnsResponse> {
+    if msg.len() < 12 || u16::from_be_bytes([msg[0], msg[1]]) != id || msg[2] & 0x80 == 0 {
+        return None;
+    }
+    Some(DnsResponse {
+        rcode: msg[3] & 0x0f,
+        answers: u16::from_be_bytes([msg[6], msg[7]]),
+        truncated: msg[2] & 0x02 != 0,
+    })
+}
+
+/// Skips the name at `pos` of a message, returning the position after it.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)? as usize;
+        // A compression pointer ends the name
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len;
+        if len == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+/// Reads the A and AAAA records of the answer section, with the lowest TTL
+/// of all answers, including the CNAME records leading to the addresses.
+fn parse_addresses(msg: &[u8]) -> Option<(Vec<IpAddr>, u32)> {
+    let questions = u16::from_be_bytes([*msg.get(4)?, *msg.get(5)?]);
+    let answers = u16::from_be_bytes([*msg.get(6)?, *msg.get(7)?]);
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    let mut ttl = u32::MAX;
+    for _ in 0..answers {
+        pos = skip_name(msg, pos)?;
+        let header = msg.get(pos..pos + 10)?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data = msg.get(pos + 10..pos + 10 + len)?;
+        match rtype {
+            1 => addrs.push(IpAddr::from(<[u8; 4]>::try_from(data).ok()?)),
+            28 => addrs.push(IpAddr::from(<[u8; 16]>::try_from(data).ok()?)),
+            _ => {}
+        }
+        ttl = ttl.min(u32::from_be_bytes([
+            header[4], header[5], header[6], header[7],
+        ]));
+        pos += 10 + len;
+    }
+    Some((addrs, ttl))
+}
+
+async fn query_udp(server: SocketAddr, query: &[u8], id: u16) -> Result<Vec<u8>> {
+    let socket = udp::connect(server).await?;
+    socket.send(query).await?;
+
+    let mut buf = vec![0u8; MAX_UDP_MESSAGE];
+    loop {
+        let n = udp::recv(&socket, &mut buf).await?;
+        if parse_response(&buf[..n], id).is_some() {
+            buf.truncate(n);
+            return Ok(buf);
+        }
+    }
+}
+
+async fn query_tcp(server: SocketAddr, query: &[u8], id: u16) -> Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(server)
+        .await
+        .context("TCP connection failed")?;
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await?;
+
+    let len = stream
+        .read_u16()
+        .await
+        .context("Resolver closed the TCP connection")? as usize;
+    let mut msg = vec![0u8; len];
+    stream.read_exact(&mut msg).await?;
+    parse_response(&msg, id).ok_or_else(|| anyhow!("Invalid DNS response over TCP"))?;
+    Ok(msg)
+}
+
+/// Asks `question` to the resolver over UDP, retrying over TCP when the
+/// answer is truncated. Returns the header and the whole message of the
+/// response, and whether the UDP answer was truncated.
+async fn exchange(
+    server: SocketAddr,
+    question: &DnsQuestion,
+) -> Result<(DnsResponse, Vec<u8>, bool)> {
+    let id = fastrand::u16(..);
+    let query = encode_query(id, question)?;
+    let mut msg = query_udp(server, &query, id).await?;
+    let truncated = parse_response(&msg, id).is_some_and(|response| response.truncated);
+    if truncated {
+        msg = query_tcp(server, &query, id).await?;
+    }
+    let response = parse_response(&msg, id).ok_or_else(|| anyhow!("Invalid DNS response"))?;
+    Ok((response, msg, truncated))
+}
+
+async fn resolve(server: SocketAddr, question: &DnsQuestion) -> Result<DnsDetail> {
+    let (response, _, truncated) = exchange(server, question).await?;
+    Ok(DnsDetail {
+        rcode: rcode_label(response.rcode),
+        answers: response.answers,
+        truncated,
+    })
+}
+
+/// Addresses of `name` for each of the record types `qtypes`, asked to the
+/// resolver at `server`, and how long they may be cached.
+pub async fn lookup(
+    server: SocketAddr,
+    name: &str,
+    qtypes: &[u16],
+) -> Result<(Vec<IpAddr>, Duration)> {
+    let mut addrs = Vec::new();
+    let mut ttl = u32::MAX;
+    for &qtype in qtypes {
+        let question = DnsQuestion {
+            name: name.to_string(),
+            qtype,
+        };
+        let (response, msg, _) = exchange(server, &question).await?;
+        if response.rcode != 0 {
+            return Err(anyhow!("{}", rcode_label(response.rcode)));
+        }
+        let (found, found_ttl) =
+            parse_addresses(&msg).ok_or_else(|| anyhow!("Invalid DNS response"))?;
+        if !found.is_empty() {
+            ttl = ttl.min(found_ttl);
+        }
+        addrs.extend(found);
+    }
+    if addrs.is_empty() {
+        return Err(anyhow!("No IP address found for host"));
+    }
+    Ok((addrs, Duration::from_secs(ttl.into())))
+}
+
+/// Checks that a DNS server answers on `addr`, with a query about the root
+/// zone.
+pub async fn probe_server(addr: SocketAddr) -> Result<()> {
+    let question = DnsQuestion {
+        name: ".".to_string(),
+        qtype: 2,
+    };
+    resolve(addr, &question).await.map(|_| ())
+}
+
+/// Times DNS queries sent to a resolver.
+pub struct DnsPinger {
+    target_name: String,
+    target: IpAddr,
+    server: SocketAddr,
+    question: DnsQuestion,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl DnsPinger {
+    pub fn new(
+        target_name: String,
+        target_url: Url,
+        target: IpAddr,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let question = DnsQuestion::from_url(&target_url)?;
+        // Fail early on names that cannot be encoded
+        encode_query(0, &question)?;
+        let port = target_url.port().unwrap_or(DEFAULT_PORT);
+        Ok(Self {
+            target_name,
+            target,
+            server: SocketAddr::new(target, port),
+            question,
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        })
+    }
+}
+
+#[async_trait]
+impl Pinger for DnsPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let server = self.server;
+        let question = self.question.clone();
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+
+            let (status, rtt, detail) =
+                match tokio::time::timeout(timeout, resolve(server, &question)).await {
+                    Ok(Ok(detail)) => {
+                        let rtt = start.elapsed();
+                        // NXDOMAIN is a valid answer; other errors mean the
+                        // resolver could not do its job
+                        let status = match detail.rcode {
+                            "NOERROR" | "NXDOMAIN" => ProbeStatus::Success,
+                            rcode => ProbeStatus::Unhealthy(rcode.to_string()),
+                        };
+                        (status, rtt, Some(ProbeDetail::Dns(detail)))
+                    }
+                    Ok(Err(e)) => (ProbeStatus::Error(format!("{:#}", e)), Duration::ZERO, None),
+                    Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+                };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: 0,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_question_from_url() {
+        let url = Url::parse("dns://1.1.1.1/example.com?type=aaaa").unwrap();
+        let question = DnsQuestion::from_url(&url).unwrap();
+        assert_eq!(question.name, "example.com");
+        assert_eq!(question.qtype, 28);
+
+        let url = Url::parse("dns://1.1.1.1:5353").unwrap();
+        assert_eq!(DnsQuestion::from_url(&url).unwrap().name, ".");
+        assert_eq!(parse_record_type("TYPE65").unwrap(), 65);
+        assert!(parse_record_type("BOGUS").is_err());
+    }
+
+    #[test]
+    fn test_encode_query() {
+        let question = DnsQuestion {
+            name: "a.io.".to_string(),
+            qtype: 28,
+        };
+        let query = encode_query(0x1234, &question).unwrap();
+        assert_eq!(&query[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&query[12..], &[1, b'a', 2, b'i', b'o', 0, 0, 28, 0, 1]);
+
+        let root = DnsQuestion {
+            name: ".".to_string(),
+            qtype: 2,
+        };
+        assert_eq!(&encode_query(0, &root).unwrap()[12..], &[0, 0, 2, 0, 1]);
+
+        let bad = DnsQuestion {
+            name: "a..io".to_string(),
+            qtype: 1,
+        };
+        assert!(encode_query(0, &bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        // SERVFAIL, truncated, two answers
+        let msg = [0x12, 0x34, 0x83, 0x82, 0, 1, 0, 2, 0, 0, 0, 0];
+        let response = parse_response(&msg, 0x1234).unwrap();
+        assert_eq!(
+            response,
+            DnsResponse {
+                rcode: 2,
+                answers: 2,
+                truncated: true
+            }
+        );
+        assert!(parse_response(&msg, 0x4321).is_none());
+        // A query, not a response
+        assert!(parse_response(&[0x12, 0x34, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0], 0x1234).is_none());
+    }
+
+    #[test]
+    fn test_parse_addresses() {
+        let mut msg = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 3, 0, 0, 0, 0];
+        // www.a.io IN A
+        msg.extend_from_slice(&[3, b'w', b'w', b'w', 1, b'a', 2, b'i', b'o', 0, 0, 1, 0, 1]);
+        // www.a.io CNAME a.io, TTL 300
+        msg.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 2, 0xc0, 16]);
+        // a.io A 192.0.2.1 and 192.0.2.2, TTL 60
+        for last in [1, 2] {
+            msg.extend_from_slice(&[0xc0, 16, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, last]);
+        }
+
+        let (addrs, ttl) = parse_addresses(&msg).unwrap();
+        assert_eq!(
+            addrs,
+            [
+                "192.0.2.1".parse::<IpAddr>().unwrap(),
+                "192.0.2.2".parse().unwrap()
+            ]
+        );
+        assert_eq!(ttl, 60);
+        // Cut in the middle of a record
+        assert!(parse_addresses(&msg[..msg.len() - 2]).is_none());
+    }
+}
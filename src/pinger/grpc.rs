@@ -0,0 +1,375 @@
+use crate::pinger::Pinger;
+use crate::pinger::http::client::HttpExchange;
+use crate::pinger::http::{HttpClient, HttpOptions, HttpVersion, TlsError};
+use crate::session::{GrpcDetail, HttpDetail, PingResult, ProbeDetail, ProbeStatus};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header::{CONTENT_TYPE, HeaderMap, HeaderValue, TE};
+use http::{Method, StatusCode};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use url::Url;
+
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// Returns the HTTP/2 URL of the health check behind a `grpc://` or
+/// `grpcs://` target, and the service named by the target's path.
+///
+/// `grpcs://` uses TLS. Without a port, 443 and 80 are assumed like for
+/// HTTPS and HTTP.
+pub fn health_check_url(target: &Url) -> Result<(Url, String)> {
+    let scheme = match target.scheme() {
+        "grpc" => "http",
+        "grpcs" => "https",
+        scheme => return Err(anyhow!("Unsupported gRPC scheme '{}'", scheme)),
+    };
+    let host = target
+        .host_str()
+        .ok_or_else(|| anyhow!("URL has no host: {}", target))?;
+    let port = target
+        .port()
+        .unwrap_or(if scheme == "https" { 443 } else { 80 });
+    let url = Url::parse(&format!(
+        "{}://{}:{}{}",
+        scheme, host, port, HEALTH_CHECK_PATH
+    ))?;
+    let service = target.path().trim_matches('/').to_string();
+    Ok((url, service))
+}
+
+/// Serving status of a `HealthCheckResponse`.
+fn serving_status_label(status: u64) -> &'static str {
+    match status {
+        0 => "UNKNOWN",
+        1 => "SERVING",
+        2 => "NOT_SERVING",
+        3 => "SERVICE_UNKNOWN",
+        _ => "INVALID",
+    }
+}
+
+fn grpc_status_label(code: u32) -> &'static str {
+    match code {
+        0 => "OK",
+        1 => "CANCELLED",
+        2 => "UNKNOWN",
+        3 => "INVALID_ARGUMENT",
+        4 => "DEADLINE_EXCEEDED",
+        5 => "NOT_FOUND",
+        6 => "ALREADY_EXISTS",
+        7 => "PERMISSION_DENIED",
+        8 => "RESOURCE_EXHAUSTED",
+        9 => "FAILED_PRECONDITION",
+        10 => "ABORTED",
+        11 => "OUT_OF_RANGE",
+        12 => "UNIMPLEMENTED",
+        13 => "INTERNAL",
+        14 => "UNAVAILABLE",
+        15 => "DATA_LOSS",
+        16 => "UNAUTHENTICATED",
+        _ => "UNKNOWN_CODE",
+    }
+}
+
+/// Encodes `HealthCheckRequest { service }` in a gRPC message frame.
+fn encode_request(service: &str) -> Bytes {
+    let mut message = Vec::new();
+    if !service.is_empty() {
+        // Field 1, length delimited
+        message.push(0x0a);
+        put_varint(&mut message, service.len() as u64);
+        message.extend_from_slice(service.as_bytes());
+    }
+    let mut frame = vec![0u8];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+    Bytes::from(frame)
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn get_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or_else(|| anyhow!("truncated varint"))?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("invalid varint"))
+}
+
+/// Decodes the serving status from a framed `HealthCheckResponse`, skipping
+/// unknown fields. A missing status field means UNKNOWN.
+fn decode_response(body: &[u8]) -> Result<u64> {
+    if body.len() < 5 {
+        return Err(anyhow!("empty health check response"));
+    }
+    if body[0] != 0 {
+        return Err(anyhow!("compressed responses are not supported"));
+    }
+    let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    let mut message = body
+        .get(5..5 + len)
+        .ok_or_else(|| anyhow!("truncated health check response"))?;
+
+    let mut status = 0;
+    while !message.is_empty() {
+        let key = get_varint(&mut message)?;
+        let skip = match key & 0x7 {
+            0 => {
+                let value = get_varint(&mut message)?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+                0
+            }
+            1 => 8,
+            2 => get_varint(&mut message)? as usize,
+            5 => 4,
+            wire_type => return Err(anyhow!("invalid wire type {}", wire_type)),
+        };
+        message = message
+            .get(skip..)
+            .ok_or_else(|| anyhow!("truncated health check response"))?;
+    }
+    Ok(status)
+}
+
+/// Calls `grpc.health.v1.Health/Check` for a service over HTTP/2.
+pub struct GrpcPinger {
+    target_name: String,
+    target_ip: IpAddr,
+    timeout: Duration,
+    url: Url,
+    client: Arc<HttpClient>,
+    headers: HeaderMap,
+    request: Bytes,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl GrpcPinger {
+    pub fn new(
+        target_name: String,
+        target_url: Url,
+        target_ip: IpAddr,
+        timeout: Duration,
+        mut headers: HeaderMap,
+        options: &HttpOptions,
+    ) -> Result<Self> {
+        let (url, service) = health_check_url(&target_url)?;
+
+        // gRPC always runs over HTTP/2, whatever the HTTP probes use
+        let mut tls = (*options.tls).clone();
+        tls.alpn_protocols = vec![b"h2".to_vec()];
+        let options = HttpOptions {
+            version: HttpVersion::Http2,
+            max_redirects: None,
+            download: false,
+            tls: Arc::new(tls),
+            ..options.clone()
+        };
+        let client = Arc::new(HttpClient::new(url.clone(), target_ip, &options)?);
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+        headers.insert(
+            "grpc-timeout",
+            HeaderValue::from_str(&format!("{}m", timeout.as_millis().max(1)))?,
+        );
+
+        Ok(Self {
+            target_name,
+            target_ip,
+            timeout,
+            url,
+            client,
+            headers,
+            request: encode_request(&service),
+            result_tx: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Maps a health check response to a probe status: SERVING is a
+    /// success, any other serving status makes the target unhealthy, and a
+    /// failed call is an error.
+    fn evaluate(exchange: &HttpExchange) -> (ProbeStatus, Option<&'static str>) {
+        if exchange.version != http::Version::HTTP_2 {
+            return (
+                ProbeStatus::Error("server did not negotiate HTTP/2".to_string()),
+                None,
+            );
+        }
+        if exchange.status != StatusCode::OK {
+            return (
+                ProbeStatus::Error(format!("HTTP {}", exchange.status)),
+                None,
+            );
+        }
+
+        // Errors may come in the headers of a trailers-only response
+        let grpc_header = |name: &str| {
+            exchange
+                .trailers
+                .get(name)
+                .or_else(|| exchange.headers.get(name))
+                .and_then(|v| v.to_str().ok())
+        };
+        let code = match grpc_header("grpc-status").map(str::parse::<u32>) {
+            Some(Ok(code)) => code,
+            _ => {
+                return (ProbeStatus::Error("missing grpc-status".to_string()), None);
+            }
+        };
+        if code != 0 {
+            let mut msg = format!("gRPC status {} {}", code, grpc_status_label(code));
+            if let Some(message) = grpc_header("grpc-message").filter(|m| !m.is_empty()) {
+                msg.push_str(&format!(": {}", message));
+            }
+            return (ProbeStatus::Error(msg), None);
+        }
+
+        match decode_response(&exchange.body) {
+            Ok(1) => (ProbeStatus::Success, Some(serving_status_label(1))),
+            Ok(status) => {
+                let label = serving_status_label(status);
+                (ProbeStatus::Unhealthy(label.to_string()), Some(label))
+            }
+            Err(e) => (ProbeStatus::Error(format!("{:#}", e)), None),
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for GrpcPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target_ip = self.target_ip;
+        let timeout = self.timeout;
+        let url = self.url.clone();
+        let client = self.client.clone();
+        let headers = self.headers.clone();
+        let request = self.request.clone();
+
+        tokio::spawn(async move {
+            let call = client.execute(&url, Method::POST, &headers, request);
+
+            let (status, rtt, detail) = match tokio::time::timeout(timeout, call).await {
+                Ok(Ok(exchange)) => {
+                    let (status, serving) = Self::evaluate(&exchange);
+                    let detail = GrpcDetail {
+                        serving,
+                        http: HttpDetail {
+                            version: exchange.version,
+                            reused: exchange.reused,
+                            timing: exchange.timing,
+                            ..Default::default()
+                        },
+                    };
+                    (
+                        status,
+                        exchange.timing.total,
+                        Some(ProbeDetail::Grpc(detail)),
+                    )
+                }
+                Ok(Err(e)) => {
+                    let status = match e.downcast_ref::<TlsError>() {
+                        Some(tls_error) => ProbeStatus::TlsError(tls_error.to_string()),
+                        None => ProbeStatus::Error(format!("{:#}", e)),
+                    };
+                    (status, Duration::ZERO, None)
+                }
+                Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+            };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target_ip,
+                    seq,
+                    bytes: 0,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_check_url() {
+        let target = Url::parse("grpcs://api.example.com/billing.v1.Ledger").unwrap();
+        let (url, service) = health_check_url(&target).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://api.example.com/grpc.health.v1.Health/Check"
+        );
+        assert_eq!(service, "billing.v1.Ledger");
+
+        let target = Url::parse("grpc://10.0.0.1:50051").unwrap();
+        let (url, service) = health_check_url(&target).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://10.0.0.1:50051/grpc.health.v1.Health/Check"
+        );
+        assert_eq!(service, "");
+    }
+
+    #[test]
+    fn test_health_check_messages() {
+        assert_eq!(&encode_request("")[..], &[0, 0, 0, 0, 0]);
+        assert_eq!(
+            &encode_request("db")[..],
+            &[0, 0, 0, 0, 4, 0x0a, 2, b'd', b'b']
+        );
+
+        assert_eq!(decode_response(&[0, 0, 0, 0, 2, 0x08, 1]).unwrap(), 1);
+        // An unknown string field before the status is skipped
+        assert_eq!(
+            decode_response(&[0, 0, 0, 0, 5, 0x12, 1, b'x', 0x08, 2]).unwrap(),
+            2
+        );
+        assert_eq!(decode_response(&[0, 0, 0, 0, 0]).unwrap(), 0);
+        assert!(decode_response(&[0, 0, 0, 0, 4, 0x08]).is_err());
+        assert!(decode_response(&[]).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{Result, anyhow};
+use http::StatusCode;
+use regex::bytes::Regex;
+
+/// Checks applied to every HTTP response. A probe whose response fails any of
+/// them is reported as an assertion failure rather than a success.
+#[derive(Clone, Debug, Default)]
+pub struct HttpAssertions {
+    /// Accepted status codes. When empty, any 2xx or 3xx status is accepted.
+    pub statuses: Vec<u16>,
+    pub body_contains: Option<String>,
+    pub body_regex: Option<Regex>,
+    pub min_body_size: Option<usize>,
+    pub max_body_size: Option<usize>,
+}
+
+impl HttpAssertions {
+    pub fn from_cli(cli: &crate::cli::Cli) -> Result<Self> {
+        let statuses = match &cli.expect_status {
+            Some(spec) => parse_status_spec(spec)?,
+            None => Vec::new(),
+        };
+        let body_regex = match &cli.expect_body_regex {
+            Some(pattern) => Some(
+                Regex::new(pattern)
+                    .map_err(|e| anyhow!("Invalid body regex '{}': {}", pattern, e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            statuses,
+            body_contains: cli.expect_body.clone(),
+            body_regex,
+            min_body_size: cli.min_body_size,
+            max_body_size: cli.max_body_size,
+        })
+    }
+
+    /// Whether any assertion needs the response body.
+    pub fn needs_body(&self) -> bool {
+        self.body_contains.is_some() || self.body_regex.is_some()
+    }
+
+    /// Whether any assertion would fail without a response body, which HEAD
+    /// requests don't get.
+    pub fn inspects_body(&self) -> bool {
+        self.needs_body() || self.min_body_size.is_some()
+    }
+
+    /// Returns `Err` with a description of the first failed status assertion.
+    pub fn check_status(&self, status: StatusCode) -> Result<(), String> {
+        if !self.statuses.is_empty() && !self.statuses.contains(&status.as_u16()) {
+            return Err(format!("unexpected status {}", status));
+        }
+        Ok(())
+    }
+
+    /// Returns `Err` with a description of the first failed body assertion.
+    pub fn check_body(&self, body: &[u8], body_len: usize) -> Result<(), String> {
+        if let Some(min) = self.min_body_size
+            && body_len < min
+        {
+            return Err(format!("body size {} < {}", body_len, min));
+        }
+        if let Some(max) = self.max_body_size
+            && body_len > max
+        {
+            return Err(format!("body size {} > {}", body_len, max));
+        }
+        if let Some(needle) = &self.body_contains
+            && !needle.is_empty()
+            && !body.windows(needle.len()).any(|w| w == needle.as_bytes())
+        {
+            return Err(format!("body does not contain '{}'", needle));
+        }
+        if let Some(regex) = &self.body_regex
+            && !regex.is_match(body)
+        {
+            return Err(format!("body does not match /{}/", regex.as_str()));
+        }
+        Ok(())
+    }
+}
+
+/// Parses a status code list such as `200,204` or `200-299,304`.
+pub fn parse_status_spec(spec: &str) -> Result<Vec<u16>> {
+    let parse = |s: &str| -> Result<u16> {
+        let code: u16 = s
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid status code: '{}'", s.trim()))?;
+        if !(100..=999).contains(&code) {
+            return Err(anyhow!("Invalid status code: '{}'", code));
+        }
+        Ok(code)
+    };
+
+    let mut statuses = Vec::new();
+    for part in spec.split(',') {
+        if let Some((start, end)) = part.split_once('-') {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(anyhow!("Invalid status range: '{}'", part.trim()));
+            }
+            statuses.extend(start..=end);
+        } else {
+            statuses.push(parse(part)?);
+        }
+    }
+    Ok(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_spec() {
+        assert_eq!(parse_status_spec("200,204").unwrap(), vec![200, 204]);
+        assert_eq!(parse_status_spec("301-303").unwrap(), vec![301, 302, 303]);
+        assert!(parse_status_spec("abc").is_err());
+        assert!(parse_status_spec("299-200").is_err());
+        assert!(parse_status_spec("42").is_err());
+    }
+
+    #[test]
+    fn test_check_status() {
+        let assertions = HttpAssertions::default();
+        assert!(assertions.check_status(StatusCode::IM_A_TEAPOT).is_ok());
+
+        let assertions = HttpAssertions {
+            statuses: vec![200, 204],
+            ..Default::default()
+        };
+        assert!(assertions.check_status(StatusCode::OK).is_ok());
+        assert!(assertions.check_status(StatusCode::FOUND).is_err());
+    }
+
+    #[test]
+    fn test_check_body() {
+        let assertions = HttpAssertions {
+            body_contains: Some("\"status\":\"ok\"".to_string()),
+            body_regex: Some(Regex::new(r#""version":\d+"#).unwrap()),
+            min_body_size: Some(10),
+            max_body_size: Some(100),
+            ..Default::default()
+        };
+
+        let body = br#"{"status":"ok","version":3}"#;
+        assert!(assertions.check_body(body, body.len()).is_ok());
+
+        let body = br#"{"status":"error","version":3}"#;
+        let err = assertions.check_body(body, body.len()).unwrap_err();
+        assert!(err.contains("does not contain"));
+
+        let body = br#"{"status":"ok"}"#;
+        let err = assertions.check_body(body, body.len()).unwrap_err();
+        assert!(err.contains("does not match"));
+
+        let err = assertions.check_body(b"", 0).unwrap_err();
+        assert!(err.contains("body size 0 < 10"));
+        let err = assertions.check_body(b"", 1000).unwrap_err();
+        assert!(err.contains("body size 1000 > 100"));
+    }
+}
@@ -0,0 +1,634 @@
+use crate::pinger::http::{HttpOptions, TlsError};
+use crate::pinger::proxy::{Proxy, ProxyKind};
+use crate::session::HttpTiming;
+use anyhow::{Context, Result, anyhow};
+use bytes::{Buf, Bytes, BytesMut};
+use http::header::{CONTENT_LENGTH, HOST, HeaderMap, HeaderValue, PROXY_AUTHORIZATION};
+use http::{Method, Request, StatusCode, Version};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Incoming};
+use hyper::client::conn::{http1, http2};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use parking_lot::Mutex;
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// HTTP protocol version requested for probes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HttpVersion {
+    #[default]
+    Http1,
+    /// HTTP/2 negotiated with ALPN over TLS, or with prior knowledge over
+    /// cleartext. TLS servers without HTTP/2 fall back to HTTP/1.1.
+    Http2,
+    /// HTTP/3 over QUIC.
+    Http3,
+}
+
+impl HttpVersion {
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        if cli.http3 {
+            HttpVersion::Http3
+        } else if cli.http2 {
+            HttpVersion::Http2
+        } else {
+            HttpVersion::Http1
+        }
+    }
+
+    /// ALPN protocols offered in the TLS handshake.
+    pub fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        match self {
+            HttpVersion::Http1 => vec![b"http/1.1".to_vec()],
+            HttpVersion::Http2 => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            HttpVersion::Http3 => vec![b"h3".to_vec()],
+        }
+    }
+}
+
+/// Outcome of a single HTTP request.
+pub struct HttpExchange {
+    pub status: StatusCode,
+    pub version: Version,
+    /// Whether the request was sent on a kept-alive connection.
+    pub reused: bool,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// Body size from `Content-Length`, or the bytes received without one.
+    pub body_len: usize,
+    /// Body bytes actually read, which stops early at the download limit.
+    pub received: usize,
+    /// Trailers sent after the body, e.g. `grpc-status`. HTTP/3 trailers
+    /// are not read.
+    pub trailers: HeaderMap,
+    pub timing: HttpTiming,
+}
+
+/// Reads a response body, counting every byte but only keeping them when
+/// they are inspected, and stopping once `limit` bytes have arrived.
+struct BodyReader {
+    keep: bool,
+    limit: Option<usize>,
+    buf: BytesMut,
+    received: usize,
+    trailers: HeaderMap,
+}
+
+impl BodyReader {
+    fn new(keep: bool, limit: Option<usize>) -> Self {
+        Self {
+            keep,
+            limit,
+            buf: BytesMut::new(),
+            received: 0,
+            trailers: HeaderMap::new(),
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        let chunk = match self.limit {
+            Some(limit) => &chunk[..chunk.len().min(limit - self.received)],
+            None => chunk,
+        };
+        self.received += chunk.len();
+        if self.keep {
+            self.buf.extend_from_slice(chunk);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.received >= limit)
+    }
+}
+
+/// An established connection that can carry further requests.
+enum Connection {
+    Http1(http1::SendRequest<Full<Bytes>>),
+    Http2(http2::SendRequest<Full<Bytes>>),
+    Http3(
+        h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>,
+        quinn::Connection,
+    ),
+}
+
+impl Connection {
+    fn is_usable(&self) -> bool {
+        match self {
+            Connection::Http1(sender) => !sender.is_closed() && sender.is_ready(),
+            Connection::Http2(sender) => !sender.is_closed() && sender.is_ready(),
+            Connection::Http3(_, conn) => conn.close_reason().is_none(),
+        }
+    }
+}
+
+/// A small HTTP client that times every phase of a request.
+///
+/// The client always connects to the address selected at startup. New
+/// connections still resolve the URL host so that the DNS phase reflects what
+/// a regular client would pay. Unless `reuse` is off, idle connections are
+/// kept alive and reused by later requests, in which case the connection
+/// phases are zero. `http+unix://` URLs connect to their Unix domain socket
+/// instead, without a DNS phase.
+pub struct HttpClient {
+    url: Url,
+    addr: SocketAddr,
+    socket: Option<PathBuf>,
+    version: HttpVersion,
+    reuse: bool,
+    server_name: ServerName<'static>,
+    tls_config: Arc<ClientConfig>,
+    proxy: Option<Arc<Proxy>>,
+    /// Whether response bodies are kept for the assertions.
+    keep_body: bool,
+    body_limit: Option<usize>,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl HttpClient {
+    pub fn new(url: Url, target_ip: IpAddr, options: &HttpOptions) -> Result<Self> {
+        let version = options.version;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let socket = crate::pinger::unix::http_socket_path(&url);
+
+        let server_name = match url.host() {
+            // Never used, as there is no TLS over the socket
+            _ if socket.is_some() => ServerName::from(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            Some(url::Host::Domain(domain)) => ServerName::try_from(domain.to_string())
+                .map_err(|e| anyhow!("Invalid TLS server name '{}': {}", domain, e))?,
+            Some(url::Host::Ipv4(ip)) => ServerName::from(IpAddr::V4(ip)),
+            Some(url::Host::Ipv6(ip)) => ServerName::from(IpAddr::V6(ip)),
+            None => return Err(anyhow!("URL has no host: {}", url)),
+        };
+
+        if version == HttpVersion::Http3 && url.scheme() != "https" {
+            return Err(anyhow!("HTTP/3 requires an https:// URL"));
+        }
+        if version == HttpVersion::Http3 && options.proxy.is_some() {
+            return Err(anyhow!("HTTP/3 cannot be used through a proxy"));
+        }
+        if socket.is_some() && options.proxy.is_some() {
+            return Err(anyhow!("HTTP over a Unix socket cannot use a proxy"));
+        }
+        if socket.is_some() && cfg!(not(unix)) {
+            return Err(anyhow!(
+                "Unix domain sockets are not supported on this platform"
+            ));
+        }
+
+        Ok(Self {
+            url,
+            addr: SocketAddr::new(target_ip, port),
+            socket,
+            version,
+            reuse: options.reuse,
+            server_name,
+            tls_config: options.tls.clone(),
+            proxy: options.proxy.clone(),
+            keep_body: !options.download || options.assertions.needs_body(),
+            body_limit: options.download_limit,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Sends one request and reads the response body, up to the download
+    /// limit. `url` must have the same origin as the URL the client was
+    /// created for.
+    pub async fn execute(
+        &self,
+        url: &Url,
+        method: Method,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> Result<HttpExchange> {
+        let mut timing = HttpTiming::default();
+        let start = Instant::now();
+
+        let idle = if self.reuse { self.take_idle() } else { None };
+        let reused = idle.is_some();
+        let mut conn = match idle {
+            Some(conn) => conn,
+            None => self.connect(&mut timing).await?,
+        };
+
+        let mut reader = BodyReader::new(self.keep_body, self.body_limit);
+        // An HTTP/1.1 connection is left mid-response when a download is cut
+        // short, while HTTP/2 and HTTP/3 simply reset the stream
+        let mut reusable = true;
+        let parts = match &mut conn {
+            Connection::Http1(sender) => {
+                let request = self.build_request(url, method, headers, Full::new(body), false)?;
+                let sent = Instant::now();
+                let response = sender.send_request(request).await.map_err(request_error)?;
+                timing.ttfb = sent.elapsed();
+                let (parts, body) = response.into_parts();
+                reusable = Self::read_body(body, &mut reader, &mut timing).await?;
+                parts
+            }
+            Connection::Http2(sender) => {
+                let request = self.build_request(url, method, headers, Full::new(body), true)?;
+                let sent = Instant::now();
+                let response = sender.send_request(request).await.map_err(request_error)?;
+                timing.ttfb = sent.elapsed();
+                let (parts, body) = response.into_parts();
+                Self::read_body(body, &mut reader, &mut timing).await?;
+                parts
+            }
+            Connection::Http3(sender, _) => {
+                let request = self.build_request(url, method, headers, (), true)?;
+                let sent = Instant::now();
+                let mut stream = sender
+                    .send_request(request)
+                    .await
+                    .context("Failed to send request")?;
+                if !body.is_empty() {
+                    stream
+                        .send_data(body)
+                        .await
+                        .context("Failed to send request body")?;
+                }
+                stream.finish().await.context("Failed to send request")?;
+                let response = stream
+                    .recv_response()
+                    .await
+                    .context("Failed to read response")?;
+                timing.ttfb = sent.elapsed();
+
+                let received = Instant::now();
+                while !reader.is_full()
+                    && let Some(mut chunk) = stream
+                        .recv_data()
+                        .await
+                        .context("Failed to read response body")?
+                {
+                    reader.push(&chunk.copy_to_bytes(chunk.remaining()));
+                }
+                timing.transfer = received.elapsed();
+
+                let (parts, _) = response.into_parts();
+                parts
+            }
+        };
+        timing.total = start.elapsed();
+
+        if self.reuse && reusable {
+            self.idle.lock().push(conn);
+        } else if let Connection::Http3(_, quic) = conn {
+            quic.close(0u32.into(), b"");
+        }
+
+        let body_len = parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(reader.received);
+
+        Ok(HttpExchange {
+            status: parts.status,
+            version: parts.version,
+            reused,
+            headers: parts.headers,
+            body: reader.buf.freeze(),
+            body_len,
+            received: reader.received,
+            trailers: reader.trailers,
+            timing,
+        })
+    }
+
+    /// Reads an HTTP/1.1 or HTTP/2 body into `reader`. Returns whether the
+    /// body was read to the end.
+    async fn read_body(
+        mut body: Incoming,
+        reader: &mut BodyReader,
+        timing: &mut HttpTiming,
+    ) -> Result<bool> {
+        let received = Instant::now();
+        let mut complete = true;
+        while let Some(frame) = body.frame().await {
+            let frame = frame.context("Failed to read response body")?;
+            if let Some(data) = frame.data_ref() {
+                reader.push(data);
+            } else if let Some(trailers) = frame.trailers_ref() {
+                reader.trailers = trailers.clone();
+            }
+            if reader.is_full() && !body.is_end_stream() {
+                complete = false;
+                break;
+            }
+        }
+        timing.transfer = received.elapsed();
+        Ok(complete)
+    }
+
+    fn take_idle(&self) -> Option<Connection> {
+        let mut idle = self.idle.lock();
+        while let Some(conn) = idle.pop() {
+            if conn.is_usable() {
+                return Some(conn);
+            }
+        }
+        None
+    }
+
+    /// Whether requests are forwarded by an HTTP proxy instead of being
+    /// tunnelled through it, which is how plain HTTP/1.1 is proxied.
+    fn forwarded(&self) -> bool {
+        matches!(&self.proxy, Some(proxy) if proxy.kind == ProxyKind::Http)
+            && self.url.scheme() == "http"
+            && self.version == HttpVersion::Http1
+    }
+
+    async fn connect(&self, timing: &mut HttpTiming) -> Result<Connection> {
+        #[cfg(unix)]
+        if let Some(path) = &self.socket {
+            let t = Instant::now();
+            let stream = tokio::net::UnixStream::connect(path)
+                .await
+                .context("Connection failed")?;
+            timing.connect = t.elapsed();
+            return match self.version {
+                HttpVersion::Http2 => Self::handshake_h2(stream).await,
+                _ => Self::handshake_h1(stream).await,
+            };
+        }
+
+        let tcp = match &self.proxy {
+            // The proxy resolves and connects to the target
+            Some(proxy) => {
+                let t = Instant::now();
+                let mut tcp = proxy.connect().await?;
+                timing.proxy = t.elapsed();
+                if self.forwarded() {
+                    return Self::handshake_h1(tcp).await;
+                }
+
+                let host = self.url.host_str().unwrap_or_default();
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                let t = Instant::now();
+                proxy.tunnel(&mut tcp, host, self.addr.port()).await?;
+                timing.connect = t.elapsed();
+                tcp
+            }
+            None => {
+                if let Some(url::Host::Domain(domain)) = self.url.host() {
+                    let t = Instant::now();
+                    let _ = tokio::net::lookup_host((domain, self.addr.port()))
+                        .await
+                        .context(format!("Failed to resolve host: {}", domain))?;
+                    timing.dns = t.elapsed();
+                }
+
+                if self.version == HttpVersion::Http3 {
+                    return self.connect_quic(timing).await;
+                }
+
+                let t = Instant::now();
+                let tcp = TcpStream::connect(self.addr)
+                    .await
+                    .context("Connection failed")?;
+                tcp.set_nodelay(true)?;
+                timing.connect = t.elapsed();
+                tcp
+            }
+        };
+
+        if self.url.scheme() != "https" {
+            // Cleartext HTTP/2 is only possible with prior knowledge
+            return match self.version {
+                HttpVersion::Http2 => Self::handshake_h2(tcp).await,
+                _ => Self::handshake_h1(tcp).await,
+            };
+        }
+
+        let t = Instant::now();
+        let stream = TlsConnector::from(self.tls_config.clone())
+            .connect(self.server_name.clone(), tcp)
+            .await
+            .map_err(|e| match TlsError::find(&e) {
+                Some(tls_error) => anyhow::Error::new(tls_error),
+                None => anyhow::Error::new(e).context("TLS handshake failed"),
+            })?;
+        timing.tls = t.elapsed();
+
+        if stream.get_ref().1.alpn_protocol() == Some(b"h2") {
+            Self::handshake_h2(stream).await
+        } else {
+            Self::handshake_h1(stream).await
+        }
+    }
+
+    /// Opens a QUIC connection. The QUIC handshake includes TLS, so it is
+    /// timed as a single phase.
+    async fn connect_quic(&self, timing: &mut HttpTiming) -> Result<Connection> {
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(self.tls_config.clone())
+            .context("TLS configuration is not usable for QUIC")?;
+        let bind_addr = match self.addr {
+            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let mut endpoint =
+            quinn::Endpoint::client(bind_addr).context("Failed to create QUIC endpoint")?;
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+        let server_name = match self.url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            _ => self.addr.ip().to_string(),
+        };
+
+        let t = Instant::now();
+        let conn = endpoint
+            .connect(self.addr, &server_name)
+            .context("QUIC connection failed")?
+            .await
+            .map_err(quic_error)?;
+        timing.quic = t.elapsed();
+
+        let (mut driver, sender) = h3::client::new(h3_quinn::Connection::new(conn.clone()))
+            .await
+            .context("HTTP/3 handshake failed")?;
+        tokio::spawn(async move {
+            let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+
+        Ok(Connection::Http3(sender, conn))
+    }
+
+    async fn handshake_h1<S>(io: S) -> Result<Connection>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sender, conn) = http1::handshake(TokioIo::new(io))
+            .await
+            .context("HTTP handshake failed")?;
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+        Ok(Connection::Http1(sender))
+    }
+
+    async fn handshake_h2<S>(io: S) -> Result<Connection>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sender, conn) = http2::handshake(TokioExecutor::new(), TokioIo::new(io))
+            .await
+            .context("HTTP/2 handshake failed")?;
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+        Ok(Connection::Http2(sender))
+    }
+
+    /// Builds a request. HTTP/1.1 sends an origin-form target with a Host
+    /// header, while HTTP/2 and HTTP/3 carry the authority in the URI.
+    /// Requests forwarded by a proxy use an absolute URI and a Host header.
+    fn build_request<B>(
+        &self,
+        url: &Url,
+        method: Method,
+        headers: &HeaderMap,
+        body: B,
+        absolute: bool,
+    ) -> Result<Request<B>> {
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let host = match (url.host_str(), url.port()) {
+            // The socket path is no host name, send what curl sends
+            _ if self.socket.is_some() => "localhost".to_string(),
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(anyhow!("URL has no host: {}", url)),
+        };
+
+        let forwarded = !absolute && self.forwarded();
+        let uri = if absolute || forwarded {
+            format!("{}://{}{}", url.scheme(), host, path)
+        } else {
+            path
+        };
+
+        let mut request = Request::builder().method(method).uri(uri).body(body)?;
+
+        let request_headers = request.headers_mut();
+        if !absolute {
+            request_headers.insert(HOST, HeaderValue::from_str(&host)?);
+        }
+        if forwarded && let Some(auth) = self.proxy.as_ref().and_then(|proxy| proxy.authorization())
+        {
+            request_headers.insert(PROXY_AUTHORIZATION, HeaderValue::from_str(&auth)?);
+        }
+        // User supplied headers replace the defaults above
+        request_headers.extend(headers.clone());
+
+        Ok(request)
+    }
+}
+
+fn request_error(e: hyper::Error) -> anyhow::Error {
+    match TlsError::find(&e) {
+        Some(tls_error) => anyhow::Error::new(tls_error),
+        None => anyhow::Error::new(e).context("Failed to send request"),
+    }
+}
+
+/// Maps QUIC handshake failures caused by TLS to [`TlsError`]. Peers report
+/// TLS alerts as transport errors in the crypto error range.
+fn quic_error(e: quinn::ConnectionError) -> anyhow::Error {
+    if let Some(tls_error) = TlsError::find(&e) {
+        return anyhow::Error::new(tls_error);
+    }
+    match &e {
+        quinn::ConnectionError::TransportError(t)
+            if (0x100..0x200).contains(&u64::from(t.code)) =>
+        {
+            anyhow::Error::new(TlsError(t.to_string()))
+        }
+        quinn::ConnectionError::ConnectionClosed(close)
+            if (0x100..0x200).contains(&u64::from(close.error_code)) =>
+        {
+            anyhow::Error::new(TlsError(close.to_string()))
+        }
+        _ => anyhow::Error::new(e).context("QUIC handshake failed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(version: HttpVersion, proxy: Option<&str>) -> HttpOptions {
+        let tls = crate::pinger::http::tls::client_config(&crate::pinger::http::TlsOptions {
+            insecure: true,
+            ..Default::default()
+        })
+        .unwrap();
+        HttpOptions {
+            version,
+            reuse: true,
+            max_redirects: None,
+            method: Method::HEAD,
+            body: Bytes::new(),
+            assertions: Default::default(),
+            capture_headers: Vec::new(),
+            download: false,
+            download_limit: None,
+            tls: Arc::new(tls),
+            proxy: proxy.map(|p| Arc::new(Proxy::parse(p).unwrap())),
+        }
+    }
+
+    #[test]
+    fn test_client_modes() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let http = Url::parse("http://localhost/").unwrap();
+        let https = Url::parse("https://localhost/").unwrap();
+
+        assert!(HttpClient::new(http.clone(), ip, &options(HttpVersion::Http2, None)).is_ok());
+        assert!(HttpClient::new(http.clone(), ip, &options(HttpVersion::Http3, None)).is_err());
+        assert!(HttpClient::new(https.clone(), ip, &options(HttpVersion::Http3, None)).is_ok());
+
+        let proxy = Some("http://127.0.0.1:3128");
+        let h3 = options(HttpVersion::Http3, proxy);
+        assert!(HttpClient::new(https.clone(), ip, &h3).is_err());
+
+        // Plain HTTP/1.1 is forwarded, everything else is tunnelled
+        let h1 = options(HttpVersion::Http1, proxy);
+        assert!(HttpClient::new(http.clone(), ip, &h1).unwrap().forwarded());
+        assert!(!HttpClient::new(https, ip, &h1).unwrap().forwarded());
+        let h2 = options(HttpVersion::Http2, proxy);
+        assert!(!HttpClient::new(http.clone(), ip, &h2).unwrap().forwarded());
+        let socks = options(HttpVersion::Http1, Some("socks5://127.0.0.1"));
+        assert!(!HttpClient::new(http, ip, &socks).unwrap().forwarded());
+
+        // No proxy for a Unix socket, and a placeholder Host header
+        let unix = Url::parse("http+unix://%2Frun%2Fapp.sock/health").unwrap();
+        assert!(HttpClient::new(unix.clone(), ip, &h1).is_err());
+        #[cfg(unix)]
+        {
+            let client =
+                HttpClient::new(unix.clone(), ip, &options(HttpVersion::Http1, None)).unwrap();
+            assert_eq!(client.socket, Some(PathBuf::from("/run/app.sock")));
+            let request = client
+                .build_request(&unix, Method::GET, &HeaderMap::new(), (), false)
+                .unwrap();
+            assert_eq!(request.uri(), "/health");
+            assert_eq!(request.headers()[HOST], "localhost");
+        }
+        // Unix sockets are only supported on Unix
+        #[cfg(not(unix))]
+        assert!(HttpClient::new(unix, ip, &options(HttpVersion::Http1, None)).is_err());
+    }
+}
@@ -0,0 +1,10 @@
+pub mod assertion;
+pub mod client;
+pub mod pinger;
+pub mod redirect;
+pub mod tls;
+
+pub use assertion::HttpAssertions;
+pub use client::{HttpClient, HttpVersion};
+pub use pinger::{HttpOptions, HttpPinger};
+pub use tls::{TlsError, TlsOptions};
@@ -0,0 +1,306 @@
+use crate::pinger::Pinger;
+use crate::pinger::http::client::HttpExchange;
+use crate::pinger::http::redirect::{self, RedirectFollower};
+use crate::pinger::http::{HttpAssertions, HttpClient, HttpVersion, TlsError};
+use crate::pinger::proxy::Proxy;
+use crate::session::{Download, HttpDetail, PingResult, ProbeDetail, ProbeStatus, RedirectHop};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::Method;
+use http::header::{HeaderMap, HeaderName};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use url::Url;
+
+/// Request and response settings shared by every HTTP probe.
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    pub version: HttpVersion,
+    /// Keep connections alive between probes instead of opening one per probe.
+    pub reuse: bool,
+    /// Follow redirects up to this many hops.
+    pub max_redirects: Option<usize>,
+    pub method: Method,
+    pub body: Bytes,
+    pub assertions: HttpAssertions,
+    /// Response headers whose values are reported with every probe.
+    pub capture_headers: Vec<HeaderName>,
+    /// Download the response body and report its throughput.
+    pub download: bool,
+    /// Stop each download after this many bytes.
+    pub download_limit: Option<usize>,
+    pub tls: Arc<rustls::ClientConfig>,
+    pub proxy: Option<Arc<Proxy>>,
+}
+
+/// Result of a probe that received a response.
+struct ProbeOutcome {
+    detail: HttpDetail,
+    /// The last response of the redirect chain.
+    exchange: HttpExchange,
+    total: Duration,
+    /// Why the redirect chain could not be completed.
+    failure: Option<String>,
+}
+
+pub struct HttpPinger {
+    target_name: String,
+    target_ip: IpAddr,
+    target_url: Url,
+    timeout: Duration,
+    client: Arc<HttpClient>,
+    follower: Option<Arc<RedirectFollower>>,
+    headers: HeaderMap,
+    options: Arc<HttpOptions>,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl HttpPinger {
+    pub fn new(
+        target_name: String,
+        target_url: Url,
+        target_ip: IpAddr,
+        timeout: Duration,
+        headers: HeaderMap,
+        options: HttpOptions,
+    ) -> Result<Self> {
+        let options = Arc::new(options);
+        let client = Arc::new(HttpClient::new(target_url.clone(), target_ip, &options)?);
+        let follower = options.max_redirects.map(|max_hops| {
+            Arc::new(RedirectFollower::new(
+                max_hops,
+                options.clone(),
+                &target_url,
+                client.clone(),
+                target_ip,
+            ))
+        });
+
+        Ok(Self {
+            target_name,
+            target_ip,
+            target_url,
+            timeout,
+            client,
+            follower,
+            headers,
+            options,
+            result_tx: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Sends the probe request and, when enabled, follows its redirects.
+    ///
+    /// The detail carries the phases of the first request, which went to the
+    /// target itself, while the outcome's total covers the whole chain.
+    async fn probe(
+        target_url: &Url,
+        client: Arc<HttpClient>,
+        follower: Option<&RedirectFollower>,
+        headers: &HeaderMap,
+        options: &HttpOptions,
+    ) -> Result<ProbeOutcome> {
+        let mut url = target_url.clone();
+        let mut method = options.method.clone();
+        let mut body = options.body.clone();
+        let mut headers = headers.clone();
+        let mut client = client;
+        let mut first: Option<HttpDetail> = None;
+        let mut visited = vec![url.clone()];
+        let mut hops = Vec::new();
+        let mut total = Duration::ZERO;
+
+        let (exchange, failure) = loop {
+            let exchange = client
+                .execute(&url, method.clone(), &headers, body.clone())
+                .await?;
+            total += exchange.timing.total;
+            first.get_or_insert_with(|| HttpDetail {
+                version: exchange.version,
+                reused: exchange.reused,
+                timing: exchange.timing,
+                redirects: Vec::new(),
+                headers: Vec::new(),
+                download: None,
+            });
+
+            let Some(follower) = follower else {
+                break (exchange, None);
+            };
+            let mut hop = RedirectHop {
+                url: url.to_string(),
+                status: exchange.status.as_u16(),
+                latency: exchange.timing.total,
+                downgrade: false,
+                looped: false,
+            };
+            let redirect =
+                match redirect::next_hop(&url, &method, exchange.status, &exchange.headers) {
+                    Some(Ok(redirect)) => redirect,
+                    Some(Err(e)) => {
+                        hops.push(hop);
+                        break (exchange, Some(e.to_string()));
+                    }
+                    None => {
+                        hops.push(hop);
+                        break (exchange, None);
+                    }
+                };
+
+            hop.downgrade = redirect::is_downgrade(&url, &redirect.url);
+            hop.looped = visited.contains(&redirect.url);
+            hops.push(hop);
+            if visited.contains(&redirect.url) {
+                break (exchange, Some(format!("redirect loop to {}", redirect.url)));
+            }
+            if hops.len() > follower.max_hops {
+                let msg = format!("too many redirects (max {})", follower.max_hops);
+                break (exchange, Some(msg));
+            }
+
+            headers = redirect::headers_for(&headers, &url, &redirect.url);
+            client = follower.client_for(&redirect.url).await?;
+            if !redirect.keep_body {
+                body = Bytes::new();
+            }
+            method = redirect.method;
+            visited.push(redirect.url.clone());
+            url = redirect.url;
+        };
+
+        let mut detail = first.unwrap_or_default();
+        detail.redirects = hops;
+        detail.headers = Self::capture(&options.capture_headers, &exchange.headers);
+        if options.download {
+            detail.download = Some(Download {
+                bytes: exchange.received as u64,
+                transfer: exchange.timing.transfer,
+            });
+        }
+        Ok(ProbeOutcome {
+            detail,
+            exchange,
+            total,
+            failure,
+        })
+    }
+
+    /// Values of the captured headers in a response. Repeated headers are
+    /// joined with commas.
+    fn capture(names: &[HeaderName], headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+        names
+            .iter()
+            .map(|name| {
+                let values: Vec<String> = headers
+                    .get_all(name)
+                    .iter()
+                    .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                    .collect();
+                (
+                    name.to_string(),
+                    (!values.is_empty()).then(|| values.join(", ")),
+                )
+            })
+            .collect()
+    }
+
+    /// Maps a completed exchange to a probe status, applying the assertions.
+    fn evaluate(assertions: &HttpAssertions, exchange: &HttpExchange) -> ProbeStatus {
+        let status_code = exchange.status;
+        if assertions.statuses.is_empty() {
+            if !(status_code.is_success() || status_code.is_redirection()) {
+                return ProbeStatus::Error(format!("HTTP {}", status_code));
+            }
+        } else if let Err(msg) = assertions.check_status(status_code) {
+            return ProbeStatus::AssertionFailed(msg);
+        }
+
+        match assertions.check_body(&exchange.body, exchange.body_len) {
+            Ok(()) => ProbeStatus::Success,
+            Err(msg) => ProbeStatus::AssertionFailed(msg),
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for HttpPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target_ip = self.target_ip;
+        let timeout = self.timeout;
+        let target_url = self.target_url.clone();
+        let client = self.client.clone();
+        let follower = self.follower.clone();
+        let headers = self.headers.clone();
+        let options = self.options.clone();
+
+        tokio::spawn(async move {
+            let request = Self::probe(&target_url, client, follower.as_deref(), &headers, &options);
+
+            let (status_res, rtt, bytes, detail) =
+                match tokio::time::timeout(timeout, request).await {
+                    Ok(Ok(outcome)) => {
+                        let status = match outcome.failure {
+                            Some(msg) => ProbeStatus::Error(msg),
+                            None => Self::evaluate(&options.assertions, &outcome.exchange),
+                        };
+                        let bytes = match status {
+                            ProbeStatus::Success if options.download => outcome.exchange.received,
+                            ProbeStatus::Success => outcome.exchange.body_len,
+                            _ => 0,
+                        };
+                        (
+                            status,
+                            outcome.total,
+                            bytes,
+                            Some(ProbeDetail::Http(outcome.detail)),
+                        )
+                    }
+                    Ok(Err(e)) => {
+                        let status = match e.downcast_ref::<TlsError>() {
+                            Some(tls_error) => ProbeStatus::TlsError(tls_error.to_string()),
+                            None => ProbeStatus::Error(format!("{:#}", e)),
+                        };
+                        (status, Duration::ZERO, 0, None)
+                    }
+                    Err(_) => (ProbeStatus::Timeout, Duration::ZERO, 0, None),
+                };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target_ip,
+                    seq,
+                    bytes,
+                    ttl: None,
+                    rtt,
+                    status: status_res,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use crate::pinger::http::{HttpClient, HttpOptions};
+use crate::utils::{IpVersion, resolve_host};
+use anyhow::{Result, anyhow};
+use http::header::{AUTHORIZATION, COOKIE, HOST, HeaderMap, LOCATION};
+use http::{Method, StatusCode};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use url::Url;
+
+/// The request a redirect response asks for.
+#[derive(Debug, PartialEq)]
+pub struct Redirect {
+    pub url: Url,
+    pub method: Method,
+    /// Whether the request body is sent again (307 and 308 only).
+    pub keep_body: bool,
+}
+
+/// Returns the redirect described by a response, if it is one.
+///
+/// Like browsers, 301, 302 and 303 turn any method other than HEAD into a
+/// GET without a body, while 307 and 308 repeat the request unchanged.
+pub fn next_hop(
+    url: &Url,
+    method: &Method,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Option<Result<Redirect>> {
+    let keep_method = match status.as_u16() {
+        301..=303 => false,
+        307 | 308 => true,
+        _ => return None,
+    };
+    let location = headers.get(LOCATION)?;
+
+    let next = location
+        .to_str()
+        .map_err(|_| anyhow!("Invalid Location header"))
+        .and_then(|location| {
+            url.join(location)
+                .map_err(|e| anyhow!("Invalid Location '{}': {}", location, e))
+        });
+    let next = match next {
+        Ok(next) => next,
+        Err(e) => return Some(Err(e)),
+    };
+    if next.scheme() != "http" && next.scheme() != "https" {
+        return Some(Err(anyhow!("Unsupported redirect to {}", next)));
+    }
+
+    let method = if keep_method || *method == Method::HEAD {
+        method.clone()
+    } else {
+        Method::GET
+    };
+    Some(Ok(Redirect {
+        url: next,
+        method,
+        keep_body: keep_method,
+    }))
+}
+
+/// Whether following `from` to `to` leaves HTTPS for plain HTTP.
+pub fn is_downgrade(from: &Url, to: &Url) -> bool {
+    from.scheme() == "https" && to.scheme() == "http"
+}
+
+/// Headers to send to `to` after a redirect from `from`. Credentials and a
+/// custom Host header are only kept while the origin stays the same.
+pub fn headers_for(headers: &HeaderMap, from: &Url, to: &Url) -> HeaderMap {
+    let mut headers = headers.clone();
+    if from.origin() != to.origin() {
+        headers.remove(AUTHORIZATION);
+        headers.remove(COOKIE);
+        headers.remove(HOST);
+    }
+    headers
+}
+
+/// Follows redirects for one pinger, keeping a client per origin so that
+/// connections to redirect targets are reused like the target's own.
+pub struct RedirectFollower {
+    pub max_hops: usize,
+    options: Arc<HttpOptions>,
+    prefer_ipv6: bool,
+    clients: Mutex<HashMap<String, Arc<HttpClient>>>,
+}
+
+impl RedirectFollower {
+    pub fn new(
+        max_hops: usize,
+        options: Arc<HttpOptions>,
+        target_url: &Url,
+        target_client: Arc<HttpClient>,
+        target_ip: IpAddr,
+    ) -> Self {
+        let mut clients = HashMap::new();
+        clients.insert(target_url.origin().ascii_serialization(), target_client);
+        Self {
+            max_hops,
+            options,
+            prefer_ipv6: target_ip.is_ipv6(),
+            clients: Mutex::new(clients),
+        }
+    }
+
+    /// Returns the client for the origin of `url`, creating it on first use.
+    /// New origins are resolved preferring the address family of the target,
+    /// unless a proxy resolves them.
+    pub async fn client_for(&self, url: &Url) -> Result<Arc<HttpClient>> {
+        let origin = url.origin().ascii_serialization();
+        if let Some(client) = self.clients.lock().get(&origin) {
+            return Ok(client.clone());
+        }
+
+        let ip = if self.options.proxy.is_some() {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        } else {
+            let host = url
+                .host_str()
+                .ok_or_else(|| anyhow!("URL has no host: {}", url))?
+                .trim_start_matches('[')
+                .trim_end_matches(']');
+            let addrs = resolve_host(host, IpVersion::Any).await?;
+            addrs
+                .iter()
+                .find(|ip| ip.is_ipv6() == self.prefer_ipv6)
+                .or(addrs.first())
+                .copied()
+                .ok_or_else(|| anyhow!("No address found for {}", host))?
+        };
+
+        let client = Arc::new(HttpClient::new(url.clone(), ip, &self.options)?);
+        self.clients.lock().insert(origin, client.clone());
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn location(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_next_hop() {
+        let url = Url::parse("https://example.com/a/b?x=1").unwrap();
+
+        let hop = next_hop(&url, &Method::POST, StatusCode::FOUND, &location("../c"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(hop.url.as_str(), "https://example.com/c");
+        assert_eq!(hop.method, Method::GET);
+        assert!(!hop.keep_body);
+
+        let hop = next_hop(
+            &url,
+            &Method::POST,
+            StatusCode::PERMANENT_REDIRECT,
+            &location("http://other.example/"),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(hop.method, Method::POST);
+        assert!(hop.keep_body);
+        assert!(is_downgrade(&url, &hop.url));
+
+        let hop = next_hop(&url, &Method::HEAD, StatusCode::SEE_OTHER, &location("/"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(hop.method, Method::HEAD);
+
+        assert!(next_hop(&url, &Method::GET, StatusCode::OK, &location("/")).is_none());
+        assert!(next_hop(&url, &Method::GET, StatusCode::FOUND, &HeaderMap::new()).is_none());
+        assert!(
+            next_hop(&url, &Method::GET, StatusCode::FOUND, &location("ftp://x/"))
+                .unwrap()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_headers_for() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+        headers.insert("x-probe", HeaderValue::from_static("1"));
+
+        let from = Url::parse("https://example.com/").unwrap();
+        let same = Url::parse("https://example.com/login").unwrap();
+        let other = Url::parse("https://cdn.example.com/").unwrap();
+
+        assert!(headers_for(&headers, &from, &same).contains_key(AUTHORIZATION));
+        let cross = headers_for(&headers, &from, &other);
+        assert!(!cross.contains_key(AUTHORIZATION));
+        assert!(cross.contains_key("x-probe"));
+    }
+}
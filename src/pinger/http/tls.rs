@@ -0,0 +1,204 @@
+use anyhow::{Context, Result, anyhow};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::sync::Arc;
+
+/// Certificate settings for HTTPS probes.
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    /// Skip server certificate verification.
+    pub insecure: bool,
+    /// PEM bundle of CA certificates to trust instead of the system roots.
+    pub ca_file: Option<String>,
+    /// PEM client certificate chain and private key for mutual TLS.
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+impl TlsOptions {
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        Self {
+            insecure: cli.insecure,
+            ca_file: cli.cacert.clone(),
+            client_cert: cli.cert.clone(),
+            client_key: cli.key.clone(),
+        }
+    }
+}
+
+/// A TLS handshake failure, including certificate verification errors.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct TlsError(pub String);
+
+impl TlsError {
+    /// Finds a rustls error in the source chain of `e`.
+    ///
+    /// With TLS 1.3 a server may reject the client certificate after the
+    /// handshake, so errors from sending the request are inspected too.
+    pub fn find(e: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        let mut current = Some(e);
+        while let Some(err) = current {
+            if let Some(tls) = err.downcast_ref::<rustls::Error>() {
+                return Some(Self(tls.to_string()));
+            }
+            if let Some(io) = err.downcast_ref::<std::io::Error>()
+                && let Some(tls) = io.get_ref().and_then(|i| i.downcast_ref::<rustls::Error>())
+            {
+                return Some(Self(tls.to_string()));
+            }
+            current = err.source();
+        }
+        None
+    }
+}
+
+/// Builds the rustls client configuration used for HTTPS probes.
+///
+/// Server certificates are verified against the platform trust store, or
+/// against `ca_file` when one is given, unless `insecure` is set.
+pub fn client_config(options: &TlsOptions) -> Result<ClientConfig> {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+
+    let verifier: Arc<dyn ServerCertVerifier> = if options.insecure {
+        Arc::new(NoVerifier(provider.clone()))
+    } else if let Some(path) = &options.ca_file {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(path)
+            .map_err(|e| anyhow!("Failed to read CA file {}: {}", path, e))?
+        {
+            let cert = cert.map_err(|e| anyhow!("Invalid certificate in {}: {}", path, e))?;
+            roots
+                .add(cert)
+                .map_err(|e| anyhow!("Invalid CA certificate in {}: {}", path, e))?;
+        }
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .map_err(|e| anyhow!("Failed to load CA file {}: {}", path, e))?
+    } else {
+        Arc::new(
+            rustls_platform_verifier::Verifier::new(provider.clone())
+                .context("Failed to load system certificates")?,
+        )
+    };
+
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to initialize TLS")?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+
+    let config = match (&options.client_cert, &options.client_key) {
+        (Some(cert_path), key_path) => {
+            let certs = CertificateDer::pem_file_iter(cert_path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| anyhow!("Failed to read client certificate {}: {}", cert_path, e))?;
+            // The key may live in the certificate file
+            let key_path = key_path.as_ref().unwrap_or(cert_path);
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .map_err(|e| anyhow!("Failed to read client key {}: {}", key_path, e))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .context("Invalid client certificate or key")?
+        }
+        (None, Some(_)) => return Err(anyhow!("--key requires --cert")),
+        (None, None) => builder.with_no_client_auth(),
+    };
+
+    Ok(config)
+}
+
+/// Accepts any server certificate while still checking handshake signatures.
+/// Used for `--insecure`.
+#[derive(Debug)]
+struct NoVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_config_options() {
+        let insecure = TlsOptions {
+            insecure: true,
+            ..Default::default()
+        };
+        assert!(client_config(&insecure).is_ok());
+
+        let missing_ca = TlsOptions {
+            ca_file: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        let err = client_config(&missing_ca).unwrap_err();
+        assert!(err.to_string().contains("Failed to read CA file"));
+
+        let key_only = TlsOptions {
+            insecure: true,
+            client_key: Some("client.key".to_string()),
+            ..Default::default()
+        };
+        assert!(client_config(&key_only).is_err());
+    }
+
+    #[test]
+    fn test_tls_error_find() {
+        let io = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(rustls::CertificateError::UnknownIssuer),
+        );
+        let tls_error = TlsError::find(&io).unwrap();
+        assert!(tls_error.to_string().contains("UnknownIssuer"));
+
+        let other = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(TlsError::find(&other).is_none());
+    }
+}
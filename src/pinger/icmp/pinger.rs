@@ -85,6 +85,7 @@ impl Pinger for IcmpPinger {
                 ttl: None,
                 rtt: Duration::ZERO,
                 status: ProbeStatus::Error(e.to_string()),
+                detail: None,
             })
             .await;
             return Ok(());
@@ -113,6 +114,7 @@ impl Pinger for IcmpPinger {
                                 ttl: reply.ttl,
                                 rtt,
                                 status: ProbeStatus::Success,
+                                detail: None,
                             })
                             .await;
                     }
@@ -129,6 +131,7 @@ impl Pinger for IcmpPinger {
                                 ttl: None,
                                 rtt: Duration::ZERO,
                                 status: ProbeStatus::Error("Receiver closed".into()),
+                                detail: None,
                             })
                             .await;
                     }
@@ -146,6 +149,7 @@ impl Pinger for IcmpPinger {
                                 ttl: None,
                                 rtt: Duration::ZERO,
                                 status: ProbeStatus::Timeout,
+                                detail: None,
                             })
                             .await;
                     }
@@ -1,7 +1,19 @@
+pub mod dns;
+pub mod grpc;
 pub mod http;
 pub mod icmp;
 pub mod icmp_packet;
+#[cfg(target_os = "linux")]
+pub mod neighbor;
+pub mod ntp;
+pub mod proxy;
+pub mod reflector;
+pub mod service;
+pub mod stun;
 pub mod tcp;
+pub mod udp;
+pub mod unix;
+pub mod websocket;
 
 use crate::cli::Protocol;
 use crate::pinger::icmp::IcmpClient;
@@ -20,22 +32,27 @@ pub trait Pinger: Send + Sync {
     async fn stop(&mut self) -> Result<()>;
 }
 
+#[derive(Clone)]
 pub struct PingerConfig {
     pub ttl: u32,
     pub size: usize,
     pub timeout: Duration,
     pub headers: reqwest::header::HeaderMap,
+    pub http: http::HttpOptions,
+    pub proxy: Option<Arc<proxy::Proxy>>,
+    pub interface: Option<String>,
 }
 
 pub fn create_pinger(
     target_name: String,
+    host: String,
     protocol: Protocol,
     target: IpAddr,
     config: PingerConfig,
     client_v4: Option<Arc<IcmpClient>>,
     client_v6: Option<Arc<IcmpClient>>,
-) -> Box<dyn Pinger> {
-    match protocol {
+) -> Result<Box<dyn Pinger>> {
+    let pinger: Box<dyn Pinger> = match protocol {
         Protocol::Icmp => {
             let client = if target.is_ipv6() {
                 client_v6.expect("IPv6 client needed but not provided")
@@ -53,9 +70,11 @@ pub fn create_pinger(
         }
         Protocol::Tcp(port) => Box::new(tcp::TcpPinger::new(
             target_name,
+            host,
             target,
             port,
             config.timeout,
+            config.proxy,
         )),
         Protocol::Http(url) => {
             use reqwest::Url;
@@ -67,7 +86,89 @@ pub fn create_pinger(
                 target,
                 config.timeout,
                 config.headers,
-            ))
+                config.http,
+            )?)
+        }
+        Protocol::WebSocket(url) => Box::new(websocket::WebSocketPinger::new(
+            target_name,
+            reqwest::Url::parse(&url)?,
+            target,
+            config.timeout,
+            config.headers,
+            &config.http.tls,
+            config.proxy,
+        )?),
+        Protocol::Grpc(url) => Box::new(grpc::GrpcPinger::new(
+            target_name,
+            reqwest::Url::parse(&url)?,
+            target,
+            config.timeout,
+            config.headers,
+            &config.http,
+        )?),
+        Protocol::Udp(port) => Box::new(udp::UdpPinger::new(
+            target_name,
+            target,
+            port,
+            config.size,
+            config.timeout,
+        )),
+        Protocol::Ntp(port) => Box::new(ntp::NtpPinger::new(
+            target_name,
+            target,
+            port,
+            config.timeout,
+        )),
+        Protocol::Stun(port) => Box::new(stun::StunPinger::new(
+            target_name,
+            target,
+            port,
+            config.timeout,
+        )),
+        Protocol::Reflector(port) => Box::new(reflector::ReflectorPinger::new(
+            target_name,
+            target,
+            port,
+            config.size,
+            config.timeout,
+        )),
+        Protocol::Dns(url) => Box::new(dns::DnsPinger::new(
+            target_name,
+            reqwest::Url::parse(&url)?,
+            target,
+            config.timeout,
+        )?),
+        Protocol::Redis(url) | Protocol::Memcached(url) | Protocol::Postgres(url) => {
+            Box::new(service::ServicePinger::new(
+                target_name,
+                reqwest::Url::parse(&url)?,
+                target,
+                config.timeout,
+                &config.http.tls,
+                config.proxy,
+            )?)
+        }
+        #[cfg(target_os = "linux")]
+        Protocol::Arp | Protocol::Ndp => Box::new(neighbor::NeighborPinger::new(
+            target_name,
+            target,
+            config.interface.as_deref(),
+            config.timeout,
+        )?),
+        #[cfg(not(target_os = "linux"))]
+        Protocol::Arp | Protocol::Ndp => {
+            anyhow::bail!("ARP and NDP probes are only supported on Linux")
+        }
+        #[cfg(unix)]
+        Protocol::Unix(path) => Box::new(unix::UnixPinger::new(
+            target_name,
+            path.into(),
+            config.timeout,
+        )),
+        #[cfg(not(unix))]
+        Protocol::Unix(_) => {
+            anyhow::bail!("Unix domain sockets are not supported on this platform")
         }
-    }
+    };
+    Ok(pinger)
 }
@@ -0,0 +1,699 @@
+//! Layer 2 probes: ARP requests for IPv4 and neighbor solicitations for
+//! IPv6, sent on a packet socket. Hosts that drop ICMP still have to answer
+//! them to be reachable at all.
+
+use crate::pinger::Pinger;
+use crate::session::{NeighborDetail, PingResult, ProbeDetail, ProbeStatus};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{Mutex, mpsc};
+
+pub type Mac = [u8; 6];
+
+const BROADCAST: Mac = [0xff; 6];
+const ARP_LEN: usize = 28;
+/// IPv6 header, neighbor solicitation and source link-layer address option.
+const SOLICITATION_LEN: usize = 40 + 24 + 8;
+const ICMPV6: u8 = 58;
+const NEIGHBOR_SOLICITATION: u8 = 135;
+const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// How long to keep listening after the first answer for other hosts
+/// claiming the same address.
+const CONFLICT_WINDOW: Duration = Duration::from_millis(50);
+
+/// An address configured on a local interface.
+#[derive(Clone, Debug)]
+struct Address {
+    interface: String,
+    addr: IpAddr,
+    prefix: u8,
+}
+
+fn on_subnet(addr: IpAddr, prefix: u8, target: IpAddr) -> bool {
+    match (addr, target) {
+        (IpAddr::V4(addr), IpAddr::V4(target)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            (u32::from(addr) ^ u32::from(target)) & mask == 0
+        }
+        (IpAddr::V6(addr), IpAddr::V6(target)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            (u128::from(addr) ^ u128::from(target)) & mask == 0
+        }
+        _ => false,
+    }
+}
+
+fn is_link_local(addr: IpAddr) -> bool {
+    matches!(addr, IpAddr::V6(v6) if v6.segments()[0] & 0xffc0 == 0xfe80)
+}
+
+/// Picks the interface to probe `target` on and the address to send from:
+/// the interface whose subnet contains the target, unless one is given.
+/// Neighbor solicitations are sent from the link-local address.
+fn select_source(
+    addresses: &[Address],
+    interface: Option<&str>,
+    target: IpAddr,
+) -> Result<(String, IpAddr)> {
+    let family = if target.is_ipv4() { "IPv4" } else { "IPv6" };
+    let candidates: Vec<&Address> = addresses
+        .iter()
+        .filter(|a| !a.addr.is_loopback() && a.addr.is_ipv4() == target.is_ipv4())
+        .filter(|a| interface.is_none_or(|name| a.interface == name))
+        .collect();
+
+    let matching: Vec<&&Address> = candidates
+        .iter()
+        .filter(|a| on_subnet(a.addr, a.prefix, target))
+        .collect();
+    let chosen = match (interface, matching.first()) {
+        (Some(name), None) => candidates
+            .first()
+            .with_context(|| format!("Interface {} has no {} address", name, family))?,
+        (None, None) => anyhow::bail!(
+            "No interface is on the subnet of {}; choose one with -I",
+            target
+        ),
+        (None, Some(_))
+            if matching
+                .iter()
+                .any(|a| a.interface != matching[0].interface) =>
+        {
+            anyhow::bail!("{} is on several interfaces; choose one with -I", target)
+        }
+        (_, Some(chosen)) => chosen,
+    };
+
+    let source = match target {
+        IpAddr::V4(_) => chosen.addr,
+        IpAddr::V6(_) => addresses
+            .iter()
+            .find(|a| a.interface == chosen.interface && is_link_local(a.addr))
+            .map_or(chosen.addr, |a| a.addr),
+    };
+    Ok((chosen.interface.clone(), source))
+}
+
+/// Index and MAC address of each interface, by name.
+type Links = HashMap<String, (i32, Mac)>;
+
+/// Reads the addresses of the local interfaces, and their links.
+fn read_interfaces() -> io::Result<(Vec<Address>, Links)> {
+    let mut addresses = Vec::new();
+    let mut links = HashMap::new();
+
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut cursor = ifaddrs;
+    while let Some(ifa) = unsafe { cursor.as_ref() } {
+        cursor = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+
+        match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+            libc::AF_PACKET => {
+                let ll = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_ll) };
+                if ll.sll_halen == 6 {
+                    let mut mac = [0u8; 6];
+                    mac.copy_from_slice(&ll.sll_addr[..6]);
+                    links.insert(name, (ll.sll_ifindex, mac));
+                }
+            }
+            libc::AF_INET => {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                let prefix = unsafe { ifa.ifa_netmask.cast::<libc::sockaddr_in>().as_ref() }
+                    .map_or(32, |mask| mask.sin_addr.s_addr.count_ones() as u8);
+                addresses.push(Address {
+                    interface: name,
+                    addr: Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).into(),
+                    prefix,
+                });
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                let prefix = unsafe { ifa.ifa_netmask.cast::<libc::sockaddr_in6>().as_ref() }
+                    .map_or(128, |mask| {
+                        u128::from_be_bytes(mask.sin6_addr.s6_addr).count_ones() as u8
+                    });
+                addresses.push(Address {
+                    interface: name,
+                    addr: Ipv6Addr::from(addr.sin6_addr.s6_addr).into(),
+                    prefix,
+                });
+            }
+            _ => {}
+        }
+    }
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    Ok((addresses, links))
+}
+
+/// The interface a target is probed on.
+#[derive(Clone, Debug)]
+struct Link {
+    index: i32,
+    mac: Mac,
+    source: IpAddr,
+}
+
+impl Link {
+    fn find(interface: Option<&str>, target: IpAddr) -> Result<Self> {
+        let (addresses, links) =
+            read_interfaces().context("Failed to read the network interfaces")?;
+        let (name, source) = select_source(&addresses, interface, target)?;
+        let &(index, mac) = links
+            .get(&name)
+            .with_context(|| format!("Interface {} has no Ethernet address", name))?;
+        Ok(Self { index, mac, source })
+    }
+}
+
+fn link_addr(index: i32, protocol: u16, mac: &Mac) -> libc::sockaddr_ll {
+    let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = protocol.to_be();
+    addr.sll_ifindex = index;
+    addr.sll_halen = 6;
+    addr.sll_addr[..6].copy_from_slice(mac);
+    addr
+}
+
+/// A packet socket bound to one interface and EtherType. The kernel adds
+/// and strips the Ethernet header.
+struct PacketSocket {
+    inner: AsyncFd<OwnedFd>,
+    index: i32,
+    protocol: u16,
+}
+
+impl PacketSocket {
+    fn open(index: i32, protocol: u16) -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                protocol.to_be() as i32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let addr = link_addr(index, protocol, &[0; 6]);
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const _ as *const libc::sockaddr,
+                std::mem::size_of_val(&addr) as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            inner: AsyncFd::new(fd)?,
+            index,
+            protocol,
+        })
+    }
+
+    async fn send_to(&self, packet: &[u8], dest: &Mac) -> io::Result<()> {
+        let addr = link_addr(self.index, self.protocol, dest);
+        loop {
+            let mut guard = self.inner.writable().await?;
+            let result = guard.try_io(|fd| {
+                let ret = unsafe {
+                    libc::sendto(
+                        fd.as_raw_fd(),
+                        packet.as_ptr() as *const libc::c_void,
+                        packet.len(),
+                        0,
+                        &addr as *const _ as *const libc::sockaddr,
+                        std::mem::size_of_val(&addr) as libc::socklen_t,
+                    )
+                };
+                if ret < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            });
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Receives the next incoming packet and the MAC address it came from.
+    /// Copies of packets sent from this host are skipped.
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, Mac)> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            let result = guard.try_io(|fd| {
+                let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+                let mut len = std::mem::size_of_val(&addr) as libc::socklen_t;
+                let ret = unsafe {
+                    libc::recvfrom(
+                        fd.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                        0,
+                        &mut addr as *mut _ as *mut libc::sockaddr,
+                        &mut len,
+                    )
+                };
+                if ret < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let mut mac = [0u8; 6];
+                mac.copy_from_slice(&addr.sll_addr[..6]);
+                Ok((ret as usize, addr.sll_pkttype, mac))
+            });
+            match result {
+                Ok(Ok((_, pkttype, _))) if pkttype == libc::PACKET_OUTGOING => continue,
+                Ok(Ok((n, _, mac))) => return Ok((n, mac)),
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// ARP request for `target`, from `mac` at `source`.
+fn encode_arp_request(mac: &Mac, source: Ipv4Addr, target: Ipv4Addr) -> [u8; ARP_LEN] {
+    let mut packet = [0u8; ARP_LEN];
+    // Ethernet, IPv4, address lengths, request
+    packet[..8].copy_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]);
+    packet[8..14].copy_from_slice(mac);
+    packet[14..18].copy_from_slice(&source.octets());
+    packet[24..28].copy_from_slice(&target.octets());
+    packet
+}
+
+/// The MAC address in an ARP reply from `target`.
+fn parse_arp_reply(packet: &[u8], target: Ipv4Addr) -> Option<Mac> {
+    if packet.len() < ARP_LEN
+        || packet[..8] != [0, 1, 8, 0, 6, 4, 0, 2]
+        || packet[14..18] != target.octets()
+    {
+        return None;
+    }
+    packet[8..14].try_into().ok()
+}
+
+/// The solicited-node multicast group of `target` (RFC 4291, 2.7.1).
+fn solicited_node(target: Ipv6Addr) -> Ipv6Addr {
+    let o = target.octets();
+    Ipv6Addr::new(
+        0xff02,
+        0,
+        0,
+        0,
+        0,
+        1,
+        0xff00 | o[13] as u16,
+        u16::from_be_bytes([o[14], o[15]]),
+    )
+}
+
+/// The Ethernet address an IPv6 multicast group is sent to (RFC 2464, 7).
+fn multicast_mac(group: Ipv6Addr) -> Mac {
+    let o = group.octets();
+    [0x33, 0x33, o[12], o[13], o[14], o[15]]
+}
+
+fn icmpv6_checksum(source: Ipv6Addr, dest: Ipv6Addr, message: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(40 + message.len());
+    pseudo.extend_from_slice(&source.octets());
+    pseudo.extend_from_slice(&dest.octets());
+    pseudo.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    pseudo.extend_from_slice(&[0, 0, 0, ICMPV6]);
+    pseudo.extend_from_slice(message);
+
+    let mut sum: u32 = pseudo
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Neighbor solicitation for `target` in an IPv6 packet to its
+/// solicited-node group, carrying `mac` as the source link-layer address.
+fn encode_solicitation(mac: &Mac, source: Ipv6Addr, target: Ipv6Addr) -> [u8; SOLICITATION_LEN] {
+    let dest = solicited_node(target);
+    let mut packet = [0u8; SOLICITATION_LEN];
+    packet[0] = 0x60;
+    packet[4..6].copy_from_slice(&((SOLICITATION_LEN - 40) as u16).to_be_bytes());
+    packet[6] = ICMPV6;
+    // Hop limit 255 proves the packet was not forwarded
+    packet[7] = 255;
+    packet[8..24].copy_from_slice(&source.octets());
+    packet[24..40].copy_from_slice(&dest.octets());
+
+    let message = &mut packet[40..];
+    message[0] = NEIGHBOR_SOLICITATION;
+    message[8..24].copy_from_slice(&target.octets());
+    message[24] = 1; // source link-layer address
+    message[25] = 1; // in units of 8 bytes
+    message[26..32].copy_from_slice(mac);
+    let checksum = icmpv6_checksum(source, dest, message);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// The MAC address in a neighbor advertisement for `target`: the target
+/// link-layer address option, or else the `sender` of the frame.
+fn parse_advertisement(packet: &[u8], target: Ipv6Addr, sender: Mac) -> Option<Mac> {
+    if packet.len() < 40 + 24
+        || packet[0] >> 4 != 6
+        || packet[6] != ICMPV6
+        || packet[40] != NEIGHBOR_ADVERTISEMENT
+        || packet[48..64] != target.octets()
+    {
+        return None;
+    }
+    let mut options = &packet[64..];
+    while options.len() >= 8 {
+        let len = options[1] as usize * 8;
+        if len == 0 || len > options.len() {
+            break;
+        }
+        if options[0] == 2 && len == 8 {
+            return options[2..8].try_into().ok();
+        }
+        options = &options[len..];
+    }
+    Some(sender)
+}
+
+/// Records that `macs` answered for `target`, and returns the other targets
+/// the same MAC addresses answered for, across all pingers.
+fn claims(macs: &[Mac], target: IpAddr) -> Vec<IpAddr> {
+    static CLAIMS: OnceLock<std::sync::Mutex<HashMap<Mac, Vec<IpAddr>>>> = OnceLock::new();
+    let mut claims = CLAIMS.get_or_init(Default::default).lock().unwrap();
+    let mut shared_with = Vec::new();
+    for mac in macs {
+        let targets = claims.entry(*mac).or_default();
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+        for other in targets.iter() {
+            if *other != target && !shared_with.contains(other) {
+                shared_with.push(*other);
+            }
+        }
+    }
+    shared_with
+}
+
+/// Sends ARP requests (IPv4) or neighbor solicitations (IPv6) on the local
+/// link and reports the MAC address that answers.
+pub struct NeighborPinger {
+    target_name: String,
+    target: IpAddr,
+    link: Link,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl NeighborPinger {
+    pub fn new(
+        target_name: String,
+        target: IpAddr,
+        interface: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        Ok(Self {
+            target_name,
+            target,
+            link: Link::find(interface, target)?,
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn request(link: &Link, target: IpAddr) -> (u16, Vec<u8>, Mac) {
+        match (link.source, target) {
+            (IpAddr::V4(source), IpAddr::V4(target)) => (
+                libc::ETH_P_ARP as u16,
+                encode_arp_request(&link.mac, source, target).to_vec(),
+                BROADCAST,
+            ),
+            (IpAddr::V6(source), IpAddr::V6(target)) => (
+                libc::ETH_P_IPV6 as u16,
+                encode_solicitation(&link.mac, source, target).to_vec(),
+                multicast_mac(solicited_node(target)),
+            ),
+            _ => unreachable!("the source address is of the target's family"),
+        }
+    }
+
+    /// Waits for the next answer for `target`.
+    async fn answer(socket: &PacketSocket, target: IpAddr) -> io::Result<Mac> {
+        let mut buf = [0u8; 1500];
+        loop {
+            let (n, sender) = socket.recv(&mut buf).await?;
+            let mac = match target {
+                IpAddr::V4(target) => parse_arp_reply(&buf[..n], target),
+                IpAddr::V6(target) => parse_advertisement(&buf[..n], target, sender),
+            };
+            if let Some(mac) = mac {
+                return Ok(mac);
+            }
+        }
+    }
+
+    /// Sends one request and returns the first answer with its round trip,
+    /// and the other MAC addresses that answered shortly after.
+    async fn probe(
+        link: &Link,
+        target: IpAddr,
+        timeout: Duration,
+    ) -> Result<Option<(Duration, Mac, Vec<Mac>)>> {
+        let (protocol, packet, dest) = Self::request(link, target);
+        let socket =
+            PacketSocket::open(link.index, protocol).context("Failed to open packet socket")?;
+
+        let start = Instant::now();
+        socket.send_to(&packet, &dest).await?;
+        let mac = match tokio::time::timeout(timeout, Self::answer(&socket, target)).await {
+            Ok(mac) => mac?,
+            Err(_) => return Ok(None),
+        };
+        let rtt = start.elapsed();
+
+        let window = CONFLICT_WINDOW.min(timeout.saturating_sub(rtt));
+        let deadline = tokio::time::Instant::now() + window;
+        let mut duplicates = Vec::new();
+        while let Ok(Ok(other)) =
+            tokio::time::timeout_at(deadline, Self::answer(&socket, target)).await
+        {
+            if other != mac && !duplicates.contains(&other) {
+                duplicates.push(other);
+            }
+        }
+        Ok(Some((rtt, mac, duplicates)))
+    }
+}
+
+#[async_trait]
+impl Pinger for NeighborPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let link = self.link.clone();
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let (status, rtt, detail) = match Self::probe(&link, target, timeout).await {
+                Ok(Some((rtt, mac, duplicates))) => {
+                    let macs: Vec<Mac> = std::iter::once(mac).chain(duplicates.clone()).collect();
+                    (
+                        ProbeStatus::Success,
+                        rtt,
+                        Some(ProbeDetail::Neighbor(NeighborDetail {
+                            mac,
+                            duplicates,
+                            shared_with: claims(&macs, target),
+                        })),
+                    )
+                }
+                Ok(None) => (ProbeStatus::Timeout, Duration::ZERO, None),
+                Err(e) => (ProbeStatus::Error(format!("{:#}", e)), Duration::ZERO, None),
+            };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: if target.is_ipv4() {
+                        ARP_LEN
+                    } else {
+                        SOLICITATION_LEN
+                    },
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: Mac = [0x02, 0, 0, 0, 0, 0x01];
+
+    fn address(interface: &str, addr: &str, prefix: u8) -> Address {
+        Address {
+            interface: interface.to_string(),
+            addr: addr.parse().unwrap(),
+            prefix,
+        }
+    }
+
+    #[test]
+    fn test_select_source() {
+        let addresses = [
+            address("lo", "127.0.0.1", 8),
+            address("eth0", "192.0.2.10", 24),
+            address("eth0", "2001:db8::10", 64),
+            address("eth0", "fe80::1", 64),
+            address("eth1", "198.51.100.1", 25),
+            address("eth1", "fe80::2", 64),
+        ];
+        let select = |interface, target: &str| {
+            select_source(&addresses, interface, target.parse().unwrap())
+                .map(|(name, source)| format!("{} {}", name, source))
+        };
+
+        assert_eq!(select(None, "192.0.2.77").unwrap(), "eth0 192.0.2.10");
+        assert_eq!(select(None, "198.51.100.100").unwrap(), "eth1 198.51.100.1");
+        assert!(select(None, "198.51.100.200").is_err());
+        assert_eq!(
+            select(Some("eth1"), "203.0.113.1").unwrap(),
+            "eth1 198.51.100.1"
+        );
+        // Solicitations go out from the link-local address
+        assert_eq!(select(None, "2001:db8::20").unwrap(), "eth0 fe80::1");
+        // Link-local targets are on every interface
+        assert!(select(None, "fe80::99").is_err());
+        assert_eq!(select(Some("eth1"), "fe80::99").unwrap(), "eth1 fe80::2");
+        assert!(select(Some("lo"), "127.0.0.2").is_err());
+    }
+
+    #[test]
+    fn test_arp() {
+        let source = Ipv4Addr::new(192, 0, 2, 10);
+        let target = Ipv4Addr::new(192, 0, 2, 77);
+        let request = encode_arp_request(&MAC, source, target);
+        // A request is not an answer
+        assert_eq!(parse_arp_reply(&request, target), None);
+
+        let mut reply = [0u8; ARP_LEN];
+        reply[..8].copy_from_slice(&[0, 1, 8, 0, 6, 4, 0, 2]);
+        reply[8..14].copy_from_slice(&[0xaa; 6]);
+        reply[14..18].copy_from_slice(&target.octets());
+        reply[18..24].copy_from_slice(&MAC);
+        reply[24..28].copy_from_slice(&source.octets());
+        assert_eq!(parse_arp_reply(&reply, target), Some([0xaa; 6]));
+        assert_eq!(parse_arp_reply(&reply, source), None);
+    }
+
+    #[test]
+    fn test_solicitation() {
+        let source: Ipv6Addr = "fe80::1".parse().unwrap();
+        let target: Ipv6Addr = "2001:db8::1:2345:6789".parse().unwrap();
+        let group = solicited_node(target);
+        assert_eq!(group, "ff02::1:ff45:6789".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(multicast_mac(group), [0x33, 0x33, 0xff, 0x45, 0x67, 0x89]);
+
+        let packet = encode_solicitation(&MAC, source, target);
+        assert_eq!(packet[40], NEIGHBOR_SOLICITATION);
+        // The checksum of a message including its checksum is zero
+        assert_eq!(icmpv6_checksum(source, group, &packet[40..]), 0);
+
+        let mut advert = packet;
+        advert[8..24].copy_from_slice(&target.octets());
+        advert[40] = NEIGHBOR_ADVERTISEMENT;
+        advert[64] = 2;
+        advert[66..72].copy_from_slice(&[0xaa; 6]);
+        assert_eq!(
+            parse_advertisement(&advert, target, [0xbb; 6]),
+            Some([0xaa; 6])
+        );
+        // Without a target link-layer address, the frame's sender
+        assert_eq!(
+            parse_advertisement(&advert[..64], target, [0xbb; 6]),
+            Some([0xbb; 6])
+        );
+        assert_eq!(parse_advertisement(&packet, target, [0xbb; 6]), None);
+    }
+
+    #[test]
+    fn test_claims() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(claims(&[[0x10; 6]], ip("192.0.2.1")).is_empty());
+        assert!(claims(&[[0x10; 6]], ip("192.0.2.1")).is_empty());
+        assert!(claims(&[[0x11; 6]], ip("192.0.2.2")).is_empty());
+        // A duplicate answer for .2 comes from the MAC address of .1
+        assert_eq!(
+            claims(&[[0x11; 6], [0x10; 6]], ip("192.0.2.2")),
+            vec![ip("192.0.2.1")]
+        );
+        assert_eq!(claims(&[[0x10; 6]], ip("192.0.2.1")), vec![ip("192.0.2.2")]);
+    }
+
+    #[test]
+    fn test_on_subnet() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(on_subnet(ip("10.0.0.1"), 8, ip("10.255.0.1")));
+        assert!(!on_subnet(ip("10.0.0.1"), 32, ip("10.0.0.2")));
+        assert!(on_subnet(ip("10.0.0.1"), 0, ip("192.0.2.1")));
+        assert!(!on_subnet(ip("10.0.0.1"), 8, ip("::1")));
+    }
+}
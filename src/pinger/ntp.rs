@@ -0,0 +1,234 @@
+use crate::pinger::Pinger;
+use crate::pinger::udp;
+use crate::session::{NtpDetail, PingResult, ProbeDetail, ProbeStatus};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, mpsc};
+
+pub const DEFAULT_PORT: u16 = 123;
+const PACKET_LEN: usize = 48;
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Current time as a 64-bit NTP timestamp.
+fn ntp_now() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() + UNIX_OFFSET;
+    let frac = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    (secs << 32) | frac
+}
+
+/// Signed difference `a - b` of two NTP timestamps, in seconds.
+fn diff_secs(a: u64, b: u64) -> f64 {
+    a.wrapping_sub(b) as i64 as f64 / (1u64 << 32) as f64
+}
+
+fn leap_label(leap: u8) -> &'static str {
+    match leap {
+        0 => "none",
+        1 => "+1s",
+        2 => "-1s",
+        _ => "unsync",
+    }
+}
+
+/// NTPv4 client request (LI 0, VN 4, mode 3) carrying `transmit` as its
+/// transmit timestamp.
+fn encode_request(transmit: u64) -> [u8; PACKET_LEN] {
+    let mut packet = [0u8; PACKET_LEN];
+    packet[0] = 0x23;
+    packet[40..48].copy_from_slice(&transmit.to_be_bytes());
+    packet
+}
+
+fn timestamp(packet: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(packet[offset..offset + 8].try_into().unwrap())
+}
+
+/// Reads the server reply to the request sent at `t1`, received at `t4`.
+/// Returns `None` for packets that do not answer that request.
+fn parse_reply(packet: &[u8], t1: u64, t4: u64) -> Option<NtpDetail> {
+    if packet.len() < PACKET_LEN || packet[0] & 0x07 != 4 || timestamp(packet, 24) != t1 {
+        return None;
+    }
+    let stratum = packet[1];
+    let refid = &packet[12..16];
+    let refid = if stratum <= 1 {
+        // Kiss code or reference clock name, e.g. `GPS` or `RATE`
+        String::from_utf8_lossy(refid)
+            .trim_end_matches('\0')
+            .to_string()
+    } else {
+        Ipv4Addr::new(refid[0], refid[1], refid[2], refid[3]).to_string()
+    };
+
+    let t2 = timestamp(packet, 32);
+    let t3 = timestamp(packet, 40);
+    let delay = diff_secs(t4, t1) - diff_secs(t3, t2);
+    let offset = (diff_secs(t2, t1) + diff_secs(t3, t4)) / 2.0;
+    Some(NtpDetail {
+        delay: Duration::from_secs_f64(delay.max(0.0)),
+        offset,
+        stratum,
+        refid,
+        leap: leap_label(packet[0] >> 6),
+    })
+}
+
+/// Queries an NTP server and reports its clock offset.
+pub struct NtpPinger {
+    target_name: String,
+    target: IpAddr,
+    server: SocketAddr,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl NtpPinger {
+    pub fn new(target_name: String, target: IpAddr, port: u16, timeout: Duration) -> Self {
+        Self {
+            target_name,
+            target,
+            server: SocketAddr::new(target, port),
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn query(server: SocketAddr) -> Result<NtpDetail> {
+        let socket = udp::connect(server).await?;
+        let t1 = ntp_now();
+        socket.send(&encode_request(t1)).await?;
+
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = udp::recv(&socket, &mut buf).await?;
+            if let Some(detail) = parse_reply(&buf[..n], t1, ntp_now()) {
+                return Ok(detail);
+            }
+        }
+    }
+
+    /// A server refusing service (kiss-o'-death) or not synchronized is up,
+    /// but its time must not be used.
+    fn evaluate(detail: &NtpDetail) -> ProbeStatus {
+        if detail.stratum == 0 {
+            ProbeStatus::Unhealthy(format!("kiss code {}", detail.refid))
+        } else if detail.leap == "unsync" {
+            ProbeStatus::Unhealthy("server clock not synchronized".to_string())
+        } else {
+            ProbeStatus::Success
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for NtpPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let server = self.server;
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+
+            let (status, rtt, detail) =
+                match tokio::time::timeout(timeout, Self::query(server)).await {
+                    Ok(Ok(detail)) => (
+                        Self::evaluate(&detail),
+                        start.elapsed(),
+                        Some(ProbeDetail::Ntp(detail)),
+                    ),
+                    Ok(Err(e)) => (ProbeStatus::Error(format!("{:#}", e)), Duration::ZERO, None),
+                    Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+                };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: PACKET_LEN,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ntp(secs: f64) -> u64 {
+        (secs * (1u64 << 32) as f64) as u64
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let t1 = ntp(1000.0);
+        let mut reply = [0u8; PACKET_LEN];
+        reply[0] = 0x24; // LI 0, VN 4, mode 4
+        reply[1] = 2;
+        reply[12..16].copy_from_slice(&[192, 0, 2, 1]);
+        reply[24..32].copy_from_slice(&t1.to_be_bytes());
+        // The server clock is 0.5 s ahead, the path takes 10 ms each way and
+        // the server answers in 2 ms
+        reply[32..40].copy_from_slice(&ntp(1000.510).to_be_bytes());
+        reply[40..48].copy_from_slice(&ntp(1000.512).to_be_bytes());
+
+        let detail = parse_reply(&reply, t1, ntp(1000.022)).unwrap();
+        assert!((detail.offset - 0.5).abs() < 1e-6);
+        assert!((detail.delay.as_secs_f64() - 0.020).abs() < 1e-6);
+        assert_eq!((detail.stratum, detail.refid.as_str()), (2, "192.0.2.1"));
+        assert_eq!(detail.leap, "none");
+        assert!(matches!(NtpPinger::evaluate(&detail), ProbeStatus::Success));
+
+        // A reply to another request
+        assert!(parse_reply(&reply, ntp(999.0), ntp(1000.022)).is_none());
+
+        // Kiss-o'-death
+        reply[1] = 0;
+        reply[12..16].copy_from_slice(b"RATE");
+        let detail = parse_reply(&reply, t1, ntp(1000.022)).unwrap();
+        assert!(
+            matches!(NtpPinger::evaluate(&detail), ProbeStatus::Unhealthy(msg) if msg == "kiss code RATE")
+        );
+    }
+
+    #[test]
+    fn test_diff_secs() {
+        assert_eq!(diff_secs(ntp(3.0), ntp(1.0)), 2.0);
+        assert_eq!(diff_secs(ntp(1.0), ntp(3.0)), -2.0);
+        assert_eq!(encode_request(7)[0], 0x23);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::pinger::Pinger;
+use crate::pinger::udp;
+use crate::reflector::{REPLY_LEN, Reply, encode_request, now_nanos, parse_reply};
+use crate::session::{PingResult, ProbeDetail, ProbeStatus, ReflectorDetail};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, mpsc};
+
+/// Signed difference `a - b` of two timestamps in nanoseconds, in seconds.
+fn diff_secs(a: u64, b: u64) -> f64 {
+    a.wrapping_sub(b) as i64 as f64 / 1e9
+}
+
+/// Splits the round trip of a reply received at `arrived` into its parts.
+fn measure(reply: &Reply, arrived: u64) -> ReflectorDetail {
+    ReflectorDetail {
+        forward: diff_secs(reply.received, reply.sent),
+        reverse: diff_secs(arrived, reply.reflected),
+        processing: Duration::from_nanos(reply.reflected.saturating_sub(reply.received)),
+        count: reply.count,
+    }
+}
+
+/// Sends probes to a `pingx serve` reflector.
+///
+/// Every pinger is a session of its own, so that the reflector counts the
+/// packets it receives from each one separately.
+pub struct ReflectorPinger {
+    target_name: String,
+    target: IpAddr,
+    reflector: SocketAddr,
+    session: u32,
+    size: usize,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl ReflectorPinger {
+    pub fn new(
+        target_name: String,
+        target: IpAddr,
+        port: u16,
+        size: usize,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            target_name,
+            target,
+            reflector: SocketAddr::new(target, port),
+            session: fastrand::u32(..),
+            size,
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn probe(reflector: SocketAddr, session: u32, seq: u64, size: usize) -> Result<Reply> {
+        let socket = udp::connect(reflector).await?;
+        socket
+            .send(&encode_request(session, seq, now_nanos(), size))
+            .await?;
+
+        let mut buf = vec![0u8; size.max(REPLY_LEN) + 1024];
+        loop {
+            let n = udp::recv(&socket, &mut buf).await?;
+            if let Some(reply) = parse_reply(&buf[..n])
+                && reply.session == session
+                && reply.seq == seq
+            {
+                return Ok(reply);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for ReflectorPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let reflector = self.reflector;
+        let session = self.session;
+        let size = self.size;
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let probe = Self::probe(reflector, session, seq, size);
+
+            let (status, rtt, detail) = match tokio::time::timeout(timeout, probe).await {
+                Ok(Ok(reply)) => {
+                    let detail = measure(&reply, now_nanos());
+                    // The network round trip, without the reflector's share
+                    let rtt = start.elapsed().saturating_sub(detail.processing);
+                    (
+                        ProbeStatus::Success,
+                        rtt,
+                        Some(ProbeDetail::Reflector(detail)),
+                    )
+                }
+                Ok(Err(e)) => (ProbeStatus::Error(format!("{:#}", e)), Duration::ZERO, None),
+                Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+            };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: size.max(REPLY_LEN),
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let reply = Reply {
+            session: 1,
+            seq: 1,
+            sent: 1_000_000,
+            received: 1_300_000,
+            reflected: 1_350_000,
+            count: 1,
+        };
+        // The reflector clock is behind, so the reverse delay looks negative
+        let detail = measure(&reply, 1_200_000);
+        assert!((detail.forward - 0.0003).abs() < 1e-12);
+        assert!((detail.reverse + 0.00015).abs() < 1e-12);
+        assert_eq!(detail.processing, Duration::from_micros(50));
+    }
+}
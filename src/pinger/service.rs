@@ -0,0 +1,594 @@
+//! Application protocol probes for the daemons services depend on. A TCP
+//! connect succeeds as long as the kernel accepts connections, even when the
+//! daemon itself is wedged; these probes wait for the daemon to answer.
+
+use crate::pinger::Pinger;
+use crate::pinger::http::TlsError;
+use crate::pinger::proxy::{Proxy, percent_decode};
+use crate::session::{PingResult, ProbeDetail, ProbeStatus, ServiceDetail};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc};
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// Longest reply line or PostgreSQL message read from a server.
+const MAX_REPLY_LEN: usize = 8192;
+/// PostgreSQL protocol version 3.0.
+const PG_PROTOCOL: i32 = 196608;
+/// The code of an SSLRequest, sent in place of a protocol version.
+const PG_SSL_REQUEST: i32 = 80877103;
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Service {
+    Redis,
+    Memcached,
+    Postgres,
+}
+
+impl Service {
+    pub fn from_scheme(scheme: &str) -> Option<Self> {
+        match scheme {
+            "redis" => Some(Self::Redis),
+            "memcached" => Some(Self::Memcached),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            _ => None,
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::Redis => 6379,
+            Self::Memcached => 11211,
+            Self::Postgres => 5432,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Redis => "Redis",
+            Self::Memcached => "Memcached",
+            Self::Postgres => "PostgreSQL",
+        }
+    }
+}
+
+/// The port a service URL points to.
+pub fn port(url: &Url) -> u16 {
+    url.port()
+        .or_else(|| Service::from_scheme(url.scheme()).map(Service::default_port))
+        .unwrap_or_default()
+}
+
+/// What the server answered, how the probe is judged, and how long the
+/// application exchange took.
+struct Answer {
+    status: ProbeStatus,
+    reply: String,
+    rtt: Duration,
+    tls: Option<Duration>,
+}
+
+impl Answer {
+    fn new(status: ProbeStatus, reply: String, rtt: Duration) -> Self {
+        Self {
+            status,
+            reply,
+            rtt,
+            tls: None,
+        }
+    }
+}
+
+/// Reads one CRLF-terminated reply line.
+async fn read_line<S: AsyncRead + Unpin>(reader: &mut BufReader<S>) -> Result<String> {
+    let mut line = Vec::new();
+    let n = (&mut *reader)
+        .take(MAX_REPLY_LEN as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if n == 0 {
+        return Err(anyhow!("Connection closed by server"));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(anyhow!("Reply line too long"));
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+/// Encodes a command as a RESP array of bulk strings.
+fn resp_command(args: &[&str]) -> Vec<u8> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+    }
+    command
+}
+
+/// Judges a Redis reply to PING. Errors about the server's state mean it is
+/// up but cannot serve; any other error is a protocol error.
+fn evaluate_redis(line: &str) -> ProbeStatus {
+    if line == "+PONG" {
+        return ProbeStatus::Success;
+    }
+    match line.strip_prefix('-') {
+        Some(error) => match error.split_whitespace().next().unwrap_or_default() {
+            "LOADING" | "BUSY" | "MASTERDOWN" | "MISCONF" | "TRYAGAIN" => {
+                ProbeStatus::Unhealthy(error.to_string())
+            }
+            _ => ProbeStatus::ProtocolError(error.to_string()),
+        },
+        None => ProbeStatus::ProtocolError(format!("unexpected reply '{}'", line)),
+    }
+}
+
+async fn redis<S: Stream>(stream: S, url: &Url) -> Result<Answer> {
+    let mut reader = BufReader::new(stream);
+
+    if let Some(password) = url.password() {
+        let password = percent_decode(password);
+        let user = percent_decode(url.username());
+        let command = if user.is_empty() {
+            resp_command(&["AUTH", &password])
+        } else {
+            resp_command(&["AUTH", &user, &password])
+        };
+        let start = Instant::now();
+        reader.get_mut().write_all(&command).await?;
+        let line = read_line(&mut reader).await?;
+        if line != "+OK" {
+            let error = line.strip_prefix('-').unwrap_or(&line).to_string();
+            return Ok(Answer::new(
+                ProbeStatus::ProtocolError(error),
+                line,
+                start.elapsed(),
+            ));
+        }
+    }
+
+    let start = Instant::now();
+    reader.get_mut().write_all(&resp_command(&["PING"])).await?;
+    let line = read_line(&mut reader).await?;
+    let rtt = start.elapsed();
+    let reply = line.trim_start_matches(['+', '-']).to_string();
+    Ok(Answer::new(evaluate_redis(&line), reply, rtt))
+}
+
+/// Judges a memcached reply to `version`.
+fn evaluate_memcached(line: &str) -> (ProbeStatus, String) {
+    if let Some(version) = line.strip_prefix("VERSION ") {
+        (ProbeStatus::Success, format!("version {}", version))
+    } else if let Some(error) = line.strip_prefix("SERVER_ERROR") {
+        (
+            ProbeStatus::Unhealthy(error.trim().to_string()),
+            line.to_string(),
+        )
+    } else {
+        (
+            ProbeStatus::ProtocolError(format!("unexpected reply '{}'", line)),
+            line.to_string(),
+        )
+    }
+}
+
+async fn memcached<S: Stream>(stream: S) -> Result<Answer> {
+    let mut reader = BufReader::new(stream);
+    let start = Instant::now();
+    reader.get_mut().write_all(b"version\r\n").await?;
+    let line = read_line(&mut reader).await?;
+    let rtt = start.elapsed();
+    let (status, reply) = evaluate_memcached(&line);
+    Ok(Answer::new(status, reply, rtt))
+}
+
+/// StartupMessage for `user` and `database`.
+fn pg_startup(user: &str, database: &str) -> Vec<u8> {
+    let mut body = PG_PROTOCOL.to_be_bytes().to_vec();
+    for (key, value) in [
+        ("user", user),
+        ("database", database),
+        ("application_name", "pingx"),
+    ] {
+        body.extend_from_slice(key.as_bytes());
+        body.push(0);
+        body.extend_from_slice(value.as_bytes());
+        body.push(0);
+    }
+    body.push(0);
+
+    let mut message = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+    message.extend_from_slice(&body);
+    message
+}
+
+/// Reads one backend message: its type and body.
+async fn pg_message<S: AsyncRead + Unpin + ?Sized>(stream: &mut S) -> Result<(u8, Vec<u8>)> {
+    let kind = stream
+        .read_u8()
+        .await
+        .context("Connection closed by server")?;
+    let len = stream.read_i32().await? as usize;
+    if !(4..=MAX_REPLY_LEN).contains(&len) {
+        return Err(anyhow!("Invalid PostgreSQL message length {}", len));
+    }
+    let mut body = vec![0u8; len - 4];
+    stream.read_exact(&mut body).await?;
+    Ok((kind, body))
+}
+
+/// The fields of an ErrorResponse, e.g. `C` for the SQLSTATE code and `M`
+/// for the message.
+fn pg_error_fields(body: &[u8]) -> Vec<(u8, String)> {
+    body.split(|b| *b == 0)
+        .filter(|field| !field.is_empty())
+        .map(|field| (field[0], String::from_utf8_lossy(&field[1..]).into_owned()))
+        .collect()
+}
+
+/// Judges the first message answering a StartupMessage.
+fn evaluate_postgres(kind: u8, body: &[u8]) -> (ProbeStatus, String) {
+    match kind {
+        b'R' => {
+            let method = match body
+                .get(..4)
+                .map(|c| i32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+            {
+                Some(0) => "trust",
+                Some(3) => "password",
+                Some(5) => "md5",
+                Some(7) => "gss",
+                Some(9) => "sspi",
+                Some(10) => "sasl",
+                _ => "unknown",
+            };
+            (ProbeStatus::Success, format!("auth={}", method))
+        }
+        b'E' => {
+            let fields = pg_error_fields(body);
+            let field = |code: u8| {
+                fields
+                    .iter()
+                    .find(|(c, _)| *c == code)
+                    .map_or("", |(_, value)| value.as_str())
+            };
+            let (sqlstate, message) = (field(b'C'), field(b'M'));
+            let error = format!("{} {}: {}", field(b'S'), sqlstate, message);
+            // Starting up, shutting down or out of connection slots
+            let status = if sqlstate.starts_with("53") || sqlstate.starts_with("57P") {
+                ProbeStatus::Unhealthy(error)
+            } else {
+                ProbeStatus::ProtocolError(error)
+            };
+            (status, sqlstate.to_string())
+        }
+        _ => (
+            ProbeStatus::ProtocolError(format!("unexpected message '{}'", kind as char)),
+            String::new(),
+        ),
+    }
+}
+
+/// Opens a PostgreSQL session: asks for TLS unless `sslmode=disable`, then
+/// sends the StartupMessage. A server asking for credentials is up.
+async fn postgres(
+    mut stream: TcpStream,
+    url: &Url,
+    server_name: ServerName<'static>,
+    tls_config: &Arc<ClientConfig>,
+) -> Result<Answer> {
+    let sslmode = url
+        .query_pairs()
+        .find(|(key, _)| key == "sslmode")
+        .map(|(_, value)| value.into_owned());
+
+    let mut tls = None;
+    let mut stream: Box<dyn Stream> = if sslmode.as_deref() == Some("disable") {
+        Box::new(stream)
+    } else {
+        let start = Instant::now();
+        let mut request = 8i32.to_be_bytes().to_vec();
+        request.extend_from_slice(&PG_SSL_REQUEST.to_be_bytes());
+        stream.write_all(&request).await?;
+        match stream
+            .read_u8()
+            .await
+            .context("Connection closed by server")?
+        {
+            b'S' => {
+                let stream = TlsConnector::from(tls_config.clone())
+                    .connect(server_name, stream)
+                    .await
+                    .map_err(|e| match TlsError::find(&e) {
+                        Some(tls_error) => anyhow::Error::new(tls_error),
+                        None => anyhow::Error::new(e).context("TLS handshake failed"),
+                    })?;
+                tls = Some(start.elapsed());
+                Box::new(stream)
+            }
+            b'N' if sslmode.as_deref() == Some("require") => {
+                return Err(anyhow!("Server does not support TLS"));
+            }
+            b'N' => Box::new(stream),
+            other => {
+                return Ok(Answer::new(
+                    ProbeStatus::ProtocolError(format!(
+                        "unexpected SSLRequest answer '{}'",
+                        other as char
+                    )),
+                    String::new(),
+                    start.elapsed(),
+                ));
+            }
+        }
+    };
+
+    let user = match percent_decode(url.username()) {
+        user if user.is_empty() => "postgres".to_string(),
+        user => user,
+    };
+    let database = match url.path().trim_start_matches('/') {
+        "" => user.clone(),
+        database => percent_decode(database),
+    };
+
+    let start = Instant::now();
+    stream.write_all(&pg_startup(&user, &database)).await?;
+    stream.flush().await?;
+    let (kind, body) = loop {
+        match pg_message(&mut stream).await? {
+            // Notices may precede the answer
+            (b'N', _) => continue,
+            message => break message,
+        }
+    };
+    let rtt = start.elapsed();
+
+    let (status, reply) = evaluate_postgres(kind, &body);
+    Ok(Answer {
+        status,
+        reply,
+        rtt,
+        tls,
+    })
+}
+
+/// Probes Redis with PING, memcached with `version` and PostgreSQL with the
+/// startup handshake, on a new connection each time.
+pub struct ServicePinger {
+    target_name: String,
+    target: IpAddr,
+    endpoint: Arc<Endpoint>,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+struct Endpoint {
+    service: Service,
+    url: Url,
+    addr: SocketAddr,
+    server_name: ServerName<'static>,
+    tls_config: Arc<ClientConfig>,
+    proxy: Option<Arc<Proxy>>,
+}
+
+impl ServicePinger {
+    pub fn new(
+        target_name: String,
+        url: Url,
+        target: IpAddr,
+        timeout: Duration,
+        tls_config: &ClientConfig,
+        proxy: Option<Arc<Proxy>>,
+    ) -> Result<Self> {
+        let service = Service::from_scheme(url.scheme())
+            .ok_or_else(|| anyhow!("Unsupported service scheme: {}", url.scheme()))?;
+        let server_name = match url.host() {
+            Some(url::Host::Domain(domain)) => ServerName::try_from(domain.to_string())
+                .map_err(|e| anyhow!("Invalid TLS server name '{}': {}", domain, e))?,
+            Some(url::Host::Ipv4(ip)) => ServerName::from(IpAddr::V4(ip)),
+            Some(url::Host::Ipv6(ip)) => ServerName::from(IpAddr::V6(ip)),
+            None => return Err(anyhow!("URL has no host: {}", url)),
+        };
+        // PostgreSQL negotiates TLS itself; direct ALPN is not used
+        let mut tls_config = tls_config.clone();
+        tls_config.alpn_protocols = Vec::new();
+
+        Ok(Self {
+            target_name,
+            target,
+            endpoint: Arc::new(Endpoint {
+                service,
+                addr: SocketAddr::new(target, port(&url)),
+                url,
+                server_name,
+                tls_config: Arc::new(tls_config),
+                proxy,
+            }),
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    async fn probe(endpoint: &Endpoint) -> Result<(Duration, Answer)> {
+        let start = Instant::now();
+        let tcp = match &endpoint.proxy {
+            Some(proxy) => {
+                let mut tcp = proxy.connect().await?;
+                let host = endpoint.url.host_str().unwrap_or_default();
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                proxy.tunnel(&mut tcp, host, endpoint.addr.port()).await?;
+                tcp
+            }
+            None => TcpStream::connect(endpoint.addr)
+                .await
+                .context("Connection failed")?,
+        };
+        tcp.set_nodelay(true)?;
+        let connect = start.elapsed();
+
+        let answer = match endpoint.service {
+            Service::Redis => redis(tcp, &endpoint.url).await?,
+            Service::Memcached => memcached(tcp).await?,
+            Service::Postgres => {
+                postgres(
+                    tcp,
+                    &endpoint.url,
+                    endpoint.server_name.clone(),
+                    &endpoint.tls_config,
+                )
+                .await?
+            }
+        };
+        Ok((connect, answer))
+    }
+}
+
+#[async_trait]
+impl Pinger for ServicePinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let endpoint = self.endpoint.clone();
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let (status, rtt, detail) =
+                match tokio::time::timeout(timeout, Self::probe(&endpoint)).await {
+                    Ok(Ok((connect, answer))) => (
+                        answer.status,
+                        answer.rtt,
+                        Some(ProbeDetail::Service(ServiceDetail {
+                            connect,
+                            tls: answer.tls,
+                            reply: answer.reply,
+                        })),
+                    ),
+                    Ok(Err(e)) => {
+                        let status = match e.downcast_ref::<TlsError>() {
+                            Some(tls_error) => ProbeStatus::TlsError(tls_error.to_string()),
+                            None => ProbeStatus::Error(format!("{:#}", e)),
+                        };
+                        (status, Duration::ZERO, None)
+                    }
+                    Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+                };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: 0,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redis() {
+        assert_eq!(resp_command(&["PING"]), b"*1\r\n$4\r\nPING\r\n");
+        assert!(matches!(evaluate_redis("+PONG"), ProbeStatus::Success));
+        assert!(matches!(
+            evaluate_redis("-LOADING Redis is loading the dataset in memory"),
+            ProbeStatus::Unhealthy(msg) if msg.starts_with("LOADING")
+        ));
+        assert!(matches!(
+            evaluate_redis("-NOAUTH Authentication required."),
+            ProbeStatus::ProtocolError(msg) if msg == "NOAUTH Authentication required."
+        ));
+        assert!(matches!(
+            evaluate_redis("HTTP/1.1 400 Bad Request"),
+            ProbeStatus::ProtocolError(_)
+        ));
+    }
+
+    #[test]
+    fn test_memcached() {
+        let (status, reply) = evaluate_memcached("VERSION 1.6.21");
+        assert!(matches!(status, ProbeStatus::Success));
+        assert_eq!(reply, "version 1.6.21");
+        assert!(matches!(
+            evaluate_memcached("SERVER_ERROR out of memory").0,
+            ProbeStatus::Unhealthy(msg) if msg == "out of memory"
+        ));
+        assert!(matches!(
+            evaluate_memcached("ERROR").0,
+            ProbeStatus::ProtocolError(_)
+        ));
+    }
+
+    #[test]
+    fn test_postgres() {
+        let startup = pg_startup("app", "orders");
+        assert_eq!(
+            i32::from_be_bytes(startup[..4].try_into().unwrap()) as usize,
+            startup.len()
+        );
+        assert!(startup.ends_with(b"application_name\0pingx\0\0"));
+
+        let (status, reply) = evaluate_postgres(b'R', &10i32.to_be_bytes());
+        assert!(matches!(status, ProbeStatus::Success));
+        assert_eq!(reply, "auth=sasl");
+
+        let error = b"SFATAL\0C57P03\0Mthe database system is starting up\0\0";
+        let (status, reply) = evaluate_postgres(b'E', error);
+        assert!(matches!(
+            status,
+            ProbeStatus::Unhealthy(msg) if msg == "FATAL 57P03: the database system is starting up"
+        ));
+        assert_eq!(reply, "57P03");
+
+        let error = b"SFATAL\0C28000\0Mno pg_hba.conf entry\0\0";
+        assert!(matches!(
+            evaluate_postgres(b'E', error).0,
+            ProbeStatus::ProtocolError(_)
+        ));
+    }
+
+    #[test]
+    fn test_port() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert_eq!(port(&url("redis://cache")), 6379);
+        assert_eq!(port(&url("memcached://cache:11311")), 11311);
+        assert_eq!(port(&url("postgresql://db/app")), 5432);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::pinger::Pinger;
+use crate::pinger::udp;
+use crate::session::{PingResult, ProbeDetail, ProbeStatus, StunDetail};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use parking_lot::Mutex as SyncMutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+pub const DEFAULT_PORT: u16 = 3478;
+
+const HEADER_LEN: usize = 20;
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const BINDING_ERROR: u16 = 0x0111;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+type TransactionId = [u8; 12];
+
+/// Binding Request with no attributes (RFC 8489, 6).
+fn encode_request(id: &TransactionId) -> [u8; HEADER_LEN] {
+    let mut packet = [0u8; HEADER_LEN];
+    packet[..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    packet[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    packet[8..20].copy_from_slice(id);
+    packet
+}
+
+#[derive(Debug, PartialEq)]
+enum Response {
+    /// The server reflexive address of the request.
+    Mapped(SocketAddr),
+    /// A success response without a mapped address.
+    Unmapped,
+    Error(u16, String),
+    /// ICMP port unreachable, reported by the socket.
+    PortClosed,
+}
+
+/// Reads a (XOR-)MAPPED-ADDRESS attribute value.
+fn parse_address(value: &[u8], xor: Option<&TransactionId>) -> Option<SocketAddr> {
+    let cookie = MAGIC_COOKIE.to_be_bytes();
+    let mut port = u16::from_be_bytes(value.get(2..4)?.try_into().ok()?);
+    let ip = match value.get(1)? {
+        0x01 => {
+            let mut octets: [u8; 4] = value.get(4..8)?.try_into().ok()?;
+            if xor.is_some() {
+                octets.iter_mut().zip(cookie).for_each(|(b, k)| *b ^= k);
+            }
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        0x02 => {
+            let mut octets: [u8; 16] = value.get(4..20)?.try_into().ok()?;
+            if let Some(id) = xor {
+                let key = cookie.iter().chain(id.iter());
+                octets.iter_mut().zip(key).for_each(|(b, k)| *b ^= k);
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    if xor.is_some() {
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+    }
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Parses a Binding response and returns its transaction ID. Other
+/// messages return `None`.
+fn parse_response(packet: &[u8]) -> Option<(TransactionId, Response)> {
+    if packet.len() < HEADER_LEN || packet[4..8] != MAGIC_COOKIE.to_be_bytes() {
+        return None;
+    }
+    let kind = u16::from_be_bytes([packet[0], packet[1]]);
+    let len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    let id: TransactionId = packet[8..20].try_into().ok()?;
+    let mut attrs = packet.get(HEADER_LEN..HEADER_LEN + len)?;
+
+    let mut mapped = None;
+    let mut error = None;
+    while attrs.len() >= 4 {
+        let attr = u16::from_be_bytes([attrs[0], attrs[1]]);
+        let attr_len = u16::from_be_bytes([attrs[2], attrs[3]]) as usize;
+        let value = attrs.get(4..4 + attr_len)?;
+        match attr {
+            ATTR_XOR_MAPPED_ADDRESS => mapped = parse_address(value, Some(&id)).or(mapped),
+            // Servers implementing RFC 3489 only
+            ATTR_MAPPED_ADDRESS if mapped.is_none() => mapped = parse_address(value, None),
+            ATTR_ERROR_CODE if value.len() >= 4 => {
+                let code = (value[2] & 0x07) as u16 * 100 + value[3] as u16;
+                let reason = String::from_utf8_lossy(&value[4..]).trim().to_string();
+                error = Some((code, reason));
+            }
+            _ => {}
+        }
+        // Values are padded to a multiple of 4 bytes
+        let padded = 4 + attr_len.div_ceil(4) * 4;
+        attrs = attrs.get(padded..).unwrap_or_default();
+    }
+
+    let response = match kind {
+        BINDING_SUCCESS => mapped.map_or(Response::Unmapped, Response::Mapped),
+        BINDING_ERROR => {
+            let (code, reason) = error.unwrap_or((0, String::new()));
+            Response::Error(code, reason)
+        }
+        _ => return None,
+    };
+    Some((id, response))
+}
+
+/// Probes waiting for the response to their transaction.
+type Waiters = Arc<SyncMutex<HashMap<TransactionId, oneshot::Sender<Reply>>>>;
+/// A response with its arrival time, or the error the socket reported.
+type Reply = Result<(Instant, Response), String>;
+
+/// Sends STUN Binding Requests and reports the server reflexive address.
+///
+/// All probes are sent from one socket, so that the NAT keeps a single
+/// mapping for them and a change between probes shows rebinding.
+pub struct StunPinger {
+    target_name: String,
+    target: IpAddr,
+    server: SocketAddr,
+    timeout: Duration,
+    socket: Option<Arc<UdpSocket>>,
+    waiters: Waiters,
+    recv_task: Option<JoinHandle<()>>,
+    /// The mapping reported by the latest response.
+    mapping: Arc<SyncMutex<Option<SocketAddr>>>,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl StunPinger {
+    pub fn new(target_name: String, target: IpAddr, port: u16, timeout: Duration) -> Self {
+        Self {
+            target_name,
+            target,
+            server: SocketAddr::new(target, port),
+            timeout,
+            socket: None,
+            waiters: Waiters::default(),
+            recv_task: None,
+            mapping: Arc::new(SyncMutex::new(None)),
+            result_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Hands each response to the probe waiting for it. An ICMP error fails
+    /// every pending probe, as it cannot be matched to one.
+    async fn receive(socket: Arc<UdpSocket>, waiters: Waiters) {
+        let mut buf = [0u8; 1500];
+        loop {
+            match udp::recv(&socket, &mut buf).await {
+                Ok(n) => {
+                    let arrived = Instant::now();
+                    if let Some((id, response)) = parse_response(&buf[..n])
+                        && let Some(tx) = waiters.lock().remove(&id)
+                    {
+                        let _ = tx.send(Ok((arrived, response)));
+                    }
+                }
+                Err(e) => {
+                    let arrived = Instant::now();
+                    for (_, tx) in waiters.lock().drain() {
+                        let _ = tx.send(if udp::is_port_closed(&e) {
+                            Ok((arrived, Response::PortClosed))
+                        } else {
+                            Err(e.to_string())
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn evaluate(
+        response: Response,
+        mapping: &SyncMutex<Option<SocketAddr>>,
+    ) -> (ProbeStatus, Option<ProbeDetail>) {
+        match response {
+            Response::Mapped(mapped) => {
+                let previous = mapping.lock().replace(mapped).filter(|p| *p != mapped);
+                (
+                    ProbeStatus::Success,
+                    Some(ProbeDetail::Stun(StunDetail { mapped, previous })),
+                )
+            }
+            Response::Unmapped => (
+                ProbeStatus::ProtocolError("response without a mapped address".to_string()),
+                None,
+            ),
+            Response::PortClosed => (
+                ProbeStatus::Unhealthy("port closed, host alive".to_string()),
+                None,
+            ),
+            Response::Error(code, reason) => {
+                let error = format!("error {} {}", code, reason).trim_end().to_string();
+                let status = if code >= 500 {
+                    ProbeStatus::Unhealthy(error)
+                } else {
+                    ProbeStatus::ProtocolError(error)
+                };
+                (status, None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for StunPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let socket = Arc::new(
+            udp::connect(self.server)
+                .await
+                .context("Failed to open STUN socket")?,
+        );
+        self.recv_task = Some(tokio::spawn(Self::receive(
+            socket.clone(),
+            self.waiters.clone(),
+        )));
+        self.socket = Some(socket);
+
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+        let Some(socket) = self.socket.clone() else {
+            return Ok(());
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let timeout = self.timeout;
+        let waiters = self.waiters.clone();
+        let mapping = self.mapping.clone();
+
+        tokio::spawn(async move {
+            let id: TransactionId = fastrand::u128(..).to_be_bytes()[..12].try_into().unwrap();
+            let (reply_tx, reply_rx) = oneshot::channel();
+            waiters.lock().insert(id, reply_tx);
+
+            let start = Instant::now();
+            let sent = socket.send(&encode_request(&id)).await;
+
+            let (status, rtt, detail) = match sent {
+                Err(e) => (ProbeStatus::Error(e.to_string()), Duration::ZERO, None),
+                Ok(_) => match tokio::time::timeout(timeout, reply_rx).await {
+                    Ok(Ok(Ok((arrived, response)))) => {
+                        let (status, detail) = Self::evaluate(response, &mapping);
+                        (status, arrived - start, detail)
+                    }
+                    Ok(Ok(Err(e))) => (ProbeStatus::Error(e), Duration::ZERO, None),
+                    Ok(Err(_)) | Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+                },
+            };
+            waiters.lock().remove(&id);
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: HEADER_LEN,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(task) = self.recv_task.take() {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample Binding response from RFC 5769, 2.2 (IPv4) without its
+    /// integrity and fingerprint attributes.
+    const RESPONSE_V4: [u8; 40] = [
+        0x01, 0x01, 0x00, 0x1c, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6,
+        0x86, 0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76,
+        0x65, 0x63, 0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x08,
+    ];
+
+    fn response_v4() -> Vec<u8> {
+        let mut packet = RESPONSE_V4.to_vec();
+        packet.extend_from_slice(&[0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]);
+        packet
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let (id, response) = parse_response(&response_v4()).unwrap();
+        assert_eq!(id, RESPONSE_V4[8..20]);
+        assert_eq!(
+            response,
+            Response::Mapped("192.0.2.1:32853".parse().unwrap())
+        );
+
+        // The request itself is not a response
+        let request = encode_request(&id);
+        assert_eq!(&request[..2], &[0x00, 0x01]);
+        assert!(parse_response(&request).is_none());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut packet = encode_request(&[7; 12]).to_vec();
+        packet[..2].copy_from_slice(&BINDING_ERROR.to_be_bytes());
+        let reason = b"Try Alternate";
+        packet.extend_from_slice(&ATTR_ERROR_CODE.to_be_bytes());
+        packet.extend_from_slice(&(4 + reason.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 3, 0]);
+        packet.extend_from_slice(reason);
+        packet.extend_from_slice(&[0, 0, 0]);
+        let len = (packet.len() - HEADER_LEN) as u16;
+        packet[2..4].copy_from_slice(&len.to_be_bytes());
+
+        let (_, response) = parse_response(&packet).unwrap();
+        assert_eq!(response, Response::Error(300, "Try Alternate".to_string()));
+        assert!(matches!(
+            StunPinger::evaluate(response, &SyncMutex::new(None)).0,
+            ProbeStatus::ProtocolError(msg) if msg == "error 300 Try Alternate"
+        ));
+    }
+
+    #[test]
+    fn test_mapping_change() {
+        let mapping = SyncMutex::new(None);
+        let a: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let b: SocketAddr = "203.0.113.5:40001".parse().unwrap();
+        let previous = |response| match StunPinger::evaluate(response, &mapping).1 {
+            Some(ProbeDetail::Stun(detail)) => detail.previous,
+            _ => panic!("no mapping"),
+        };
+        assert_eq!(previous(Response::Mapped(a)), None);
+        assert_eq!(previous(Response::Mapped(a)), None);
+        assert_eq!(previous(Response::Mapped(b)), Some(a));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::pinger::Pinger;
-use crate::session::{PingResult, ProbeStatus};
+use crate::pinger::proxy::Proxy;
+use crate::session::{PingResult, ProbeDetail, ProbeStatus, TcpDetail};
 use anyhow::Result;
 use async_trait::async_trait;
 use std::net::{IpAddr, SocketAddr};
@@ -10,27 +11,45 @@ use tokio::sync::{Mutex, mpsc};
 
 pub struct TcpPinger {
     target_name: String,
+    host: String,
     target: IpAddr,
     port: u16,
     timeout: std::time::Duration,
+    proxy: Option<Arc<Proxy>>,
     result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
 }
 
 impl TcpPinger {
     pub fn new(
         target_name: String,
+        host: String,
         target: IpAddr,
         port: u16,
         timeout: std::time::Duration,
+        proxy: Option<Arc<Proxy>>,
     ) -> Self {
         Self {
             target_name,
+            host,
             target,
             port,
             timeout,
+            proxy,
             result_tx: Arc::new(Mutex::new(None)),
         }
     }
+
+    /// Opens a tunnel to `host:port` through the proxy, timing both hops.
+    async fn connect_via(proxy: &Proxy, host: &str, port: u16) -> Result<TcpDetail> {
+        let start = Instant::now();
+        let mut stream = proxy.connect().await?;
+        let proxy_time = start.elapsed();
+        proxy.tunnel(&mut stream, host, port).await?;
+        Ok(TcpDetail {
+            proxy: proxy_time,
+            connect: start.elapsed() - proxy_time,
+        })
+    }
 }
 
 #[async_trait]
@@ -54,16 +73,30 @@ impl Pinger for TcpPinger {
         let timeout = self.timeout;
         let target_name = self.target_name.clone();
         let target = self.target;
+        let host = self.host.clone();
+        let port = self.port;
+        let proxy = self.proxy.clone();
 
         tokio::spawn(async move {
             let start = Instant::now();
-            let connect_future = TcpStream::connect(addr);
-            let result = tokio::time::timeout(timeout, connect_future).await;
 
-            let status = match result {
-                Ok(Ok(_stream)) => ProbeStatus::Success,
-                Ok(Err(e)) => ProbeStatus::Error(e.to_string()),
-                Err(_) => ProbeStatus::Timeout,
+            let (status, detail) = match &proxy {
+                Some(proxy) => {
+                    let connect_future = Self::connect_via(proxy, &host, port);
+                    match tokio::time::timeout(timeout, connect_future).await {
+                        Ok(Ok(detail)) => (ProbeStatus::Success, Some(ProbeDetail::Tcp(detail))),
+                        Ok(Err(e)) => (ProbeStatus::Error(format!("{:#}", e)), None),
+                        Err(_) => (ProbeStatus::Timeout, None),
+                    }
+                }
+                None => {
+                    let connect_future = TcpStream::connect(addr);
+                    match tokio::time::timeout(timeout, connect_future).await {
+                        Ok(Ok(_stream)) => (ProbeStatus::Success, None),
+                        Ok(Err(e)) => (ProbeStatus::Error(e.to_string()), None),
+                        Err(_) => (ProbeStatus::Timeout, None),
+                    }
+                }
             };
 
             let rtt = if let ProbeStatus::Success = status {
@@ -81,6 +114,7 @@ impl Pinger for TcpPinger {
                     ttl: None,
                     rtt,
                     status,
+                    detail,
                 })
                 .await;
         });
@@ -0,0 +1,230 @@
+use crate::pinger::Pinger;
+use crate::session::{PingResult, ProbeStatus};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex, mpsc};
+
+/// Sequence number and send timestamp at the start of every datagram.
+const HEADER_LEN: usize = 16;
+
+/// Opens a UDP socket connected to `addr`, so that ICMP errors about it are
+/// reported on the socket.
+pub async fn connect(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let bind: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+    Ok(socket)
+}
+
+/// Receives a datagram on a connected socket, or the error reported by an
+/// ICMP message such as port unreachable.
+///
+/// A pending ICMP error only raises the error readiness, which
+/// `UdpSocket::recv` does not wait for.
+pub async fn recv(socket: &UdpSocket, buf: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+        let ready = socket.ready(Interest::READABLE | Interest::ERROR).await?;
+        if ready.is_error() {
+            let pending = socket.take_error()?;
+            // The error readiness outlives the error, and would spin a
+            // socket that stays open for more datagrams
+            let _ = socket.try_io(Interest::ERROR, || {
+                Err::<(), _>(std::io::Error::from(ErrorKind::WouldBlock))
+            });
+            if let Some(e) = pending {
+                return Err(e);
+            }
+        }
+        match socket.try_recv(buf) {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            result => return result,
+        }
+    }
+}
+
+/// Whether an error received on a connected socket is an ICMP port
+/// unreachable. Windows reports it as a reset connection.
+pub fn is_port_closed(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset
+    )
+}
+
+/// Builds a datagram of `size` bytes: the sequence number and the send time
+/// in nanoseconds since the Unix epoch, both big endian, then filler bytes.
+fn encode_payload(seq: u64, size: usize) -> Vec<u8> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let mut payload = Vec::with_capacity(size.max(HEADER_LEN));
+    payload.extend_from_slice(&seq.to_be_bytes());
+    payload.extend_from_slice(&timestamp.to_be_bytes());
+    payload.extend((HEADER_LEN..size).map(|i| i as u8));
+    payload
+}
+
+/// Whether `reply` echoes the datagram sent with `seq`.
+fn is_echo(reply: &[u8], seq: u64) -> bool {
+    reply.len() >= HEADER_LEN && reply[..8] == seq.to_be_bytes()
+}
+
+/// Sends datagrams to a UDP port and times their echo.
+///
+/// An ICMP port unreachable answer proves that the host is up even though
+/// nothing listens on the port, so it is reported as unhealthy instead of
+/// as an error.
+pub struct UdpPinger {
+    target_name: String,
+    target: IpAddr,
+    port: u16,
+    size: usize,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl UdpPinger {
+    pub fn new(
+        target_name: String,
+        target: IpAddr,
+        port: u16,
+        size: usize,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            target_name,
+            target,
+            port,
+            size,
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Sends one datagram and waits for its echo, skipping late echoes of
+    /// other probes. Returns the size of the echo.
+    async fn echo(addr: SocketAddr, seq: u64, size: usize) -> std::io::Result<usize> {
+        let socket = connect(addr).await?;
+        socket.send(&encode_payload(seq, size)).await?;
+
+        let mut buf = vec![0u8; size.max(HEADER_LEN) + 1024];
+        loop {
+            let n = recv(&socket, &mut buf).await?;
+            if is_echo(&buf[..n], seq) {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for UdpPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let addr = SocketAddr::new(self.target, self.port);
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let size = self.size;
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+
+            let (status, bytes) =
+                match tokio::time::timeout(timeout, Self::echo(addr, seq, size)).await {
+                    Ok(Ok(bytes)) => (ProbeStatus::Success, bytes),
+                    Ok(Err(e)) if is_port_closed(&e) => (
+                        ProbeStatus::Unhealthy("port closed, host alive".to_string()),
+                        0,
+                    ),
+                    Ok(Err(e)) => (ProbeStatus::Error(e.to_string()), 0),
+                    Err(_) => (ProbeStatus::Timeout, 0),
+                };
+
+            let rtt = match status {
+                ProbeStatus::Success | ProbeStatus::Unhealthy(_) => start.elapsed(),
+                _ => Duration::ZERO,
+            };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail: None,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let payload = encode_payload(7, 56);
+        assert_eq!(payload.len(), 56);
+        assert!(is_echo(&payload, 7));
+        assert!(!is_echo(&payload, 8));
+        assert_eq!(encode_payload(1, 0).len(), HEADER_LEN);
+        assert!(!is_echo(&payload[..8], 7));
+    }
+
+    #[tokio::test]
+    async fn test_echo_and_closed_port() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+            server.send_to(&buf[..n], peer).await.unwrap();
+        });
+        assert_eq!(UdpPinger::echo(addr, 3, 64).await.unwrap(), 64);
+
+        // Nothing listens on the port of a closed socket
+        let closed = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = closed.local_addr().unwrap();
+        drop(closed);
+        let err = tokio::time::timeout(Duration::from_secs(5), UdpPinger::echo(addr, 1, 64))
+            .await
+            .expect("no port unreachable reported")
+            .unwrap_err();
+        assert!(is_port_closed(&err), "{:?}", err);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::cli::Protocol;
+#[cfg(unix)]
+use crate::pinger::Pinger;
+#[cfg(unix)]
+use crate::session::{PingResult, ProbeStatus};
+#[cfg(unix)]
+use anyhow::Result;
+#[cfg(unix)]
+use async_trait::async_trait;
+#[cfg(unix)]
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+#[cfg(unix)]
+use tokio::sync::{Mutex, mpsc};
+use url::Url;
+
+/// Scheme of HTTP targets reached over a Unix domain socket, whose host is
+/// the percent-encoded socket path, e.g. `http+unix://%2Frun%2Fapp.sock/health`.
+pub const HTTP_SCHEME: &str = "http+unix";
+
+/// Socket path of an `http+unix://` URL.
+pub fn http_socket_path(url: &Url) -> Option<PathBuf> {
+    if url.scheme() != HTTP_SCHEME {
+        return None;
+    }
+    let host = url.host_str().filter(|host| !host.is_empty())?;
+    Some(crate::pinger::proxy::percent_decode(host).into())
+}
+
+/// Socket path of targets reached over a Unix domain socket instead of the
+/// network.
+pub fn socket_path(protocol: &Protocol) -> Option<PathBuf> {
+    match protocol {
+        Protocol::Unix(path) => Some(path.into()),
+        Protocol::Http(url) => Url::parse(url).ok().and_then(|url| http_socket_path(&url)),
+        _ => None,
+    }
+}
+
+/// Times `connect()` on a Unix domain socket.
+#[cfg(unix)]
+pub struct UnixPinger {
+    target_name: String,
+    path: PathBuf,
+    timeout: Duration,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+#[cfg(unix)]
+impl UnixPinger {
+    pub fn new(target_name: String, path: PathBuf, timeout: Duration) -> Self {
+        Self {
+            target_name,
+            path,
+            timeout,
+            result_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Pinger for UnixPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let path = self.path.clone();
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let status = match tokio::time::timeout(timeout, UnixStream::connect(&path)).await {
+                Ok(Ok(_stream)) => ProbeStatus::Success,
+                Ok(Err(e)) => ProbeStatus::Error(e.to_string()),
+                Err(_) => ProbeStatus::Timeout,
+            };
+
+            let rtt = if let ProbeStatus::Success = status {
+                start.elapsed()
+            } else {
+                Duration::ZERO
+            };
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    // Shown as the target name, as there is no address
+                    target_addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    seq,
+                    bytes: 0,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail: None,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_path() {
+        let unix = Protocol::Unix("/run/app.sock".to_string());
+        assert_eq!(socket_path(&unix), Some(PathBuf::from("/run/app.sock")));
+
+        let http = Protocol::Http("http+unix://%2Fvar%2Frun%2Fdocker.sock/_ping".to_string());
+        assert_eq!(
+            socket_path(&http),
+            Some(PathBuf::from("/var/run/docker.sock"))
+        );
+
+        let tcp = Protocol::Http("http://localhost/_ping".to_string());
+        assert_eq!(socket_path(&tcp), None);
+        assert_eq!(socket_path(&Protocol::Tcp(80)), None);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::pinger::Pinger;
+use crate::pinger::http::TlsError;
+use crate::pinger::proxy::{self, Proxy};
+use crate::session::{PingResult, ProbeDetail, ProbeStatus, WsDetail, WsHandshake};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use base64::Engine;
+use http::header::HeaderMap;
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use sha1::{Digest, Sha1};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc};
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// Appended to the key to compute `Sec-WebSocket-Accept` (RFC 6455).
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Largest frame accepted from the server.
+const MAX_FRAME_LEN: u64 = 1 << 24;
+
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+/// Where and how to open the WebSocket connection.
+struct Endpoint {
+    url: Url,
+    addr: SocketAddr,
+    server_name: ServerName<'static>,
+    headers: HeaderMap,
+    tls_config: Arc<ClientConfig>,
+    proxy: Option<Arc<Proxy>>,
+}
+
+impl Endpoint {
+    /// Opens the connection and performs the upgrade handshake, timing each
+    /// phase.
+    async fn connect(&self) -> Result<(Box<dyn Stream>, WsHandshake)> {
+        let mut handshake = WsHandshake::default();
+        let host = self.url.host_str().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        let tcp = match &self.proxy {
+            // The proxy resolves and connects to the target
+            Some(proxy) => {
+                let t = Instant::now();
+                let mut tcp = proxy.connect().await?;
+                handshake.proxy = t.elapsed();
+                let t = Instant::now();
+                proxy.tunnel(&mut tcp, host, self.addr.port()).await?;
+                handshake.connect = t.elapsed();
+                tcp
+            }
+            None => {
+                if let Some(url::Host::Domain(domain)) = self.url.host() {
+                    let t = Instant::now();
+                    let _ = tokio::net::lookup_host((domain, self.addr.port()))
+                        .await
+                        .context(format!("Failed to resolve host: {}", domain))?;
+                    handshake.dns = t.elapsed();
+                }
+                let t = Instant::now();
+                let tcp = TcpStream::connect(self.addr)
+                    .await
+                    .context("Connection failed")?;
+                tcp.set_nodelay(true)?;
+                handshake.connect = t.elapsed();
+                tcp
+            }
+        };
+
+        let mut stream: Box<dyn Stream> = if self.url.scheme() == "wss" {
+            let t = Instant::now();
+            let tls = TlsConnector::from(self.tls_config.clone())
+                .connect(self.server_name.clone(), tcp)
+                .await
+                .map_err(|e| match TlsError::find(&e) {
+                    Some(tls_error) => anyhow::Error::new(tls_error),
+                    None => anyhow::Error::new(e).context("TLS handshake failed"),
+                })?;
+            handshake.tls = t.elapsed();
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+
+        let t = Instant::now();
+        self.upgrade(&mut stream).await?;
+        handshake.upgrade = t.elapsed();
+        Ok((stream, handshake))
+    }
+
+    /// Sends the HTTP/1.1 upgrade request and checks the server's answer.
+    async fn upgrade(&self, stream: &mut Box<dyn Stream>) -> Result<()> {
+        let key =
+            base64::engine::general_purpose::STANDARD.encode(fastrand::u128(..).to_be_bytes());
+        let mut path = self.url.path().to_string();
+        if let Some(query) = self.url.query() {
+            path = format!("{}?{}", path, query);
+        }
+        let host = match self.url.port() {
+            Some(port) => format!("{}:{}", self.url.host_str().unwrap_or_default(), port),
+            None => self.url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n",
+            path, host, key
+        );
+        for (name, value) in &self.headers {
+            request.push_str(&format!(
+                "{}: {}\r\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+
+        let head = proxy::read_response_head(stream)
+            .await
+            .context("Invalid upgrade response")?;
+        let mut lines = head.lines();
+        let status_line = lines.next().unwrap_or_default();
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| anyhow!("Invalid upgrade response: '{}'", status_line))?;
+        if status != 101 {
+            let reason = status_line.splitn(3, ' ').nth(2).unwrap_or_default();
+            return Err(anyhow!(
+                "WebSocket upgrade failed: HTTP {} {}",
+                status,
+                reason
+            ));
+        }
+
+        let accept = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-accept"))
+            .map(|(_, value)| value.trim().to_string());
+        if accept.as_deref() != Some(accept_key(&key).as_str()) {
+            return Err(anyhow!(
+                "WebSocket upgrade failed: invalid Sec-WebSocket-Accept"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The `Sec-WebSocket-Accept` value a server must answer `key` with.
+fn accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key, ACCEPT_GUID));
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// Writes a single masked frame, as clients must. Only used for control
+/// frames, whose payload is at most 125 bytes.
+async fn write_frame<S: AsyncWrite + Unpin + ?Sized>(
+    stream: &mut S,
+    opcode: u8,
+    payload: &[u8],
+) -> Result<()> {
+    let mask = fastrand::u32(..).to_be_bytes();
+    let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Reads one frame and returns its opcode and unmasked payload.
+async fn read_frame<S: AsyncRead + Unpin + ?Sized>(stream: &mut S) -> Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    stream
+        .read_exact(&mut header)
+        .await
+        .context("WebSocket connection closed")?;
+    let len = match header[1] & 0x7f {
+        126 => stream.read_u16().await? as u64,
+        127 => stream.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(anyhow!("WebSocket frame too large ({} bytes)", len));
+    }
+    let mask = if header[1] & 0x80 != 0 {
+        let mut mask = [0u8; 4];
+        stream.read_exact(&mut mask).await?;
+        Some(mask)
+    } else {
+        None
+    };
+
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload).await?;
+    if let Some(mask) = mask {
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok((header[0] & 0x0f, payload))
+}
+
+/// Sends a Ping carrying `seq` and waits for the matching Pong, answering
+/// the server's own Pings meanwhile. Data frames are ignored.
+async fn ping_pong<S: AsyncRead + AsyncWrite + Unpin + ?Sized>(
+    stream: &mut S,
+    seq: u64,
+) -> Result<Duration> {
+    let payload = seq.to_be_bytes();
+    let start = Instant::now();
+    write_frame(stream, OP_PING, &payload).await?;
+    loop {
+        let (opcode, data) = read_frame(stream).await?;
+        match opcode {
+            OP_PONG if data == payload => return Ok(start.elapsed()),
+            OP_PING => write_frame(stream, OP_PONG, &data).await?,
+            OP_CLOSE => {
+                let code = data.get(..2).map(|c| u16::from_be_bytes([c[0], c[1]]));
+                let reason = String::from_utf8_lossy(data.get(2..).unwrap_or_default());
+                return Err(match code {
+                    Some(code) => anyhow!("WebSocket closed by server: {} {}", code, reason),
+                    None => anyhow!("WebSocket closed by server"),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Measures round trips with Ping/Pong control frames over one upgraded
+/// connection. The connection is opened by the first probe and reopened
+/// after any failure; the probe that opens it reports the handshake.
+pub struct WebSocketPinger {
+    target_name: String,
+    target: IpAddr,
+    timeout: Duration,
+    endpoint: Arc<Endpoint>,
+    conn: Arc<Mutex<Option<Box<dyn Stream>>>>,
+    result_tx: Arc<Mutex<Option<mpsc::Sender<PingResult>>>>,
+}
+
+impl WebSocketPinger {
+    pub fn new(
+        target_name: String,
+        url: Url,
+        target: IpAddr,
+        timeout: Duration,
+        headers: HeaderMap,
+        tls_config: &ClientConfig,
+        proxy: Option<Arc<Proxy>>,
+    ) -> Result<Self> {
+        let server_name = match url.host() {
+            Some(url::Host::Domain(domain)) => ServerName::try_from(domain.to_string())
+                .map_err(|e| anyhow!("Invalid TLS server name '{}': {}", domain, e))?,
+            Some(url::Host::Ipv4(ip)) => ServerName::from(IpAddr::V4(ip)),
+            Some(url::Host::Ipv6(ip)) => ServerName::from(IpAddr::V6(ip)),
+            None => return Err(anyhow!("URL has no host: {}", url)),
+        };
+        // The upgrade is an HTTP/1.1 request whatever the HTTP probes use
+        let mut tls_config = tls_config.clone();
+        tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        Ok(Self {
+            target_name,
+            target,
+            timeout,
+            endpoint: Arc::new(Endpoint {
+                url,
+                addr: SocketAddr::new(target, port),
+                server_name,
+                headers,
+                tls_config: Arc::new(tls_config),
+                proxy,
+            }),
+            conn: Arc::new(Mutex::new(None)),
+            result_tx: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    async fn probe(
+        endpoint: &Endpoint,
+        conn: &Mutex<Option<Box<dyn Stream>>>,
+        seq: u64,
+        timeout: Duration,
+    ) -> (ProbeStatus, Duration, Option<ProbeDetail>) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let Ok(mut guard) = tokio::time::timeout_at(deadline, conn.lock()).await else {
+            return (ProbeStatus::Timeout, Duration::ZERO, None);
+        };
+
+        // The connection is only put back after a complete exchange, so a
+        // probe cut short never leaves a half-read frame behind
+        let exchange = async {
+            let (mut stream, handshake) = match guard.take() {
+                Some(stream) => (stream, None),
+                None => {
+                    let (stream, handshake) = endpoint.connect().await?;
+                    (stream, Some(handshake))
+                }
+            };
+            let rtt = ping_pong(&mut stream, seq).await?;
+            Ok::<_, anyhow::Error>((stream, handshake, rtt))
+        };
+
+        match tokio::time::timeout_at(deadline, exchange).await {
+            Ok(Ok((stream, handshake, rtt))) => {
+                *guard = Some(stream);
+                let detail = ProbeDetail::WebSocket(WsDetail { handshake });
+                (ProbeStatus::Success, rtt, Some(detail))
+            }
+            Ok(Err(e)) => {
+                let status = match e.downcast_ref::<TlsError>() {
+                    Some(tls_error) => ProbeStatus::TlsError(tls_error.to_string()),
+                    None => ProbeStatus::Error(format!("{:#}", e)),
+                };
+                (status, Duration::ZERO, None)
+            }
+            Err(_) => (ProbeStatus::Timeout, Duration::ZERO, None),
+        }
+    }
+}
+
+#[async_trait]
+impl Pinger for WebSocketPinger {
+    async fn start(&mut self, tx: mpsc::Sender<PingResult>) -> Result<()> {
+        let mut guard = self.result_tx.lock().await;
+        *guard = Some(tx);
+        Ok(())
+    }
+
+    async fn ping(&self, seq: u64) -> Result<()> {
+        let result_tx = {
+            let guard = self.result_tx.lock().await;
+            if guard.is_none() {
+                return Ok(());
+            }
+            guard.clone().unwrap()
+        };
+
+        let target_name = self.target_name.clone();
+        let target = self.target;
+        let timeout = self.timeout;
+        let endpoint = self.endpoint.clone();
+        let conn = self.conn.clone();
+
+        tokio::spawn(async move {
+            let (status, rtt, detail) = Self::probe(&endpoint, &conn, seq, timeout).await;
+
+            let _ = result_tx
+                .send(PingResult {
+                    target: target_name,
+                    target_addr: target,
+                    seq,
+                    bytes: 8,
+                    ttl: None,
+                    rtt,
+                    status,
+                    detail,
+                })
+                .await;
+        });
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(mut stream) = self.conn.lock().await.take() {
+            let _ = write_frame(&mut stream, OP_CLOSE, &1000u16.to_be_bytes()).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_accept_key() {
+        // Example from RFC 6455, section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ping_pong() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let head = proxy::read_response_head(&mut conn).await.unwrap();
+            let key = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                 Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            );
+            conn.write_all(response.as_bytes()).await.unwrap();
+
+            // A text frame and a stale Pong come before the real answer
+            let (opcode, payload) = read_frame(&mut conn).await.unwrap();
+            assert_eq!(opcode, OP_PING);
+            conn.write_all(&[0x81, 2, b'h', b'i']).await.unwrap();
+            conn.write_all(&[0x8a, 8, 0, 0, 0, 0, 0, 0, 0, 9])
+                .await
+                .unwrap();
+            let mut pong = vec![0x8a, payload.len() as u8];
+            pong.extend_from_slice(&payload);
+            conn.write_all(&pong).await.unwrap();
+
+            // Then the server closes the connection
+            read_frame(&mut conn).await.unwrap();
+            conn.write_all(&[0x88, 4, 0x03, 0xe9, b'b', b'y'])
+                .await
+                .unwrap();
+        });
+
+        let tls = crate::pinger::http::tls::client_config(&crate::pinger::http::TlsOptions {
+            insecure: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let url = Url::parse(&format!("ws://{}/chat", addr)).unwrap();
+        let pinger = WebSocketPinger::new(
+            "ws".to_string(),
+            url,
+            addr.ip(),
+            Duration::from_secs(1),
+            HeaderMap::new(),
+            &tls,
+            None,
+        )
+        .unwrap();
+
+        let (mut stream, _) = pinger.endpoint.connect().await.unwrap();
+        ping_pong(&mut stream, 1).await.unwrap();
+        let err = ping_pong(&mut stream, 2).await.unwrap_err();
+        assert_eq!(err.to_string(), "WebSocket closed by server: 1001 by");
+    }
+}
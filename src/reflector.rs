@@ -0,0 +1,217 @@
+//! A UDP reflector for two-way measurements in the spirit of TWAMP light
+//! (RFC 5357, appendix I).
+//!
+//! The sender stamps its transmit time into each packet. The reflector stamps
+//! its receive and transmit times and the number of packets it has received
+//! in the session, and sends the packet back. With both clocks synchronized,
+//! the four timestamps split the round trip into the forward and reverse
+//! one-way delays and the reflector's processing time, and the reflector's
+//! count tells which direction lost packets.
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+pub const DEFAULT_PORT: u16 = 8862;
+
+const MAGIC: &[u8; 4] = b"PGXR";
+const VERSION: u8 = 1;
+const FLAG_REPLY: u8 = 0x01;
+
+/// magic, version, flags, reserved, session, seq, sender transmit time
+const REQUEST_LEN: usize = 4 + 1 + 1 + 2 + 4 + 8 + 8;
+/// The request header, then reflector receive and transmit times and the
+/// number of packets received in the session.
+pub const REPLY_LEN: usize = REQUEST_LEN + 8 + 8 + 8;
+
+/// Sessions the reflector keeps counters for; the oldest are forgotten first.
+const MAX_SESSIONS: usize = 4096;
+
+/// Current time in nanoseconds since the Unix epoch.
+pub fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+/// Builds a request of `size` bytes, at least as large as a reply so that
+/// replies are never larger than requests.
+pub fn encode_request(session: u32, seq: u64, sent: u64, size: usize) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(size.max(REPLY_LEN));
+    packet.extend_from_slice(MAGIC);
+    packet.extend_from_slice(&[VERSION, 0, 0, 0]);
+    packet.extend_from_slice(&session.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&sent.to_be_bytes());
+    packet.resize(size.max(REPLY_LEN), 0);
+    packet
+}
+
+fn read_u64(packet: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(packet[offset..offset + 8].try_into().unwrap())
+}
+
+/// A reflected packet, with times in nanoseconds since the Unix epoch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reply {
+    pub session: u32,
+    pub seq: u64,
+    /// Sender transmit time (T1).
+    pub sent: u64,
+    /// Reflector receive time (T2).
+    pub received: u64,
+    /// Reflector transmit time (T3).
+    pub reflected: u64,
+    /// Packets of the session the reflector has received, this one included.
+    pub count: u64,
+}
+
+pub fn parse_reply(packet: &[u8]) -> Option<Reply> {
+    if packet.len() < REPLY_LEN
+        || &packet[..4] != MAGIC
+        || packet[4] != VERSION
+        || packet[5] & FLAG_REPLY == 0
+    {
+        return None;
+    }
+    Some(Reply {
+        session: u32::from_be_bytes(packet[8..12].try_into().unwrap()),
+        seq: read_u64(packet, 12),
+        sent: read_u64(packet, 20),
+        received: read_u64(packet, REQUEST_LEN),
+        reflected: read_u64(packet, REQUEST_LEN + 8),
+        count: read_u64(packet, REQUEST_LEN + 16),
+    })
+}
+
+/// Whether `packet` is a request with room for the reply.
+fn is_request(packet: &[u8]) -> bool {
+    packet.len() >= REPLY_LEN
+        && &packet[..4] == MAGIC
+        && packet[4] == VERSION
+        && packet[5] & FLAG_REPLY == 0
+}
+
+/// Turns a request into its reply in place. Returns `false` for packets
+/// that are not requests, which must not be answered.
+fn reflect(packet: &mut [u8], received: u64, count: u64) -> bool {
+    if !is_request(packet) {
+        return false;
+    }
+    packet[5] |= FLAG_REPLY;
+    packet[REQUEST_LEN..REQUEST_LEN + 8].copy_from_slice(&received.to_be_bytes());
+    packet[REQUEST_LEN + 16..REPLY_LEN].copy_from_slice(&count.to_be_bytes());
+    true
+}
+
+/// Requests received per session, with the order sessions were first seen
+/// so that the oldest are forgotten first.
+#[derive(Default)]
+struct Sessions {
+    counts: HashMap<(IpAddr, u32), u64>,
+    order: VecDeque<(IpAddr, u32)>,
+}
+
+impl Sessions {
+    /// Counts a packet from `peer` and returns the count of its session, or
+    /// `None` for packets that are not requests, which count for nothing.
+    fn record(&mut self, peer: IpAddr, packet: &[u8]) -> Option<u64> {
+        if !is_request(packet) {
+            return None;
+        }
+        let key = (peer, u32::from_be_bytes(packet[8..12].try_into().unwrap()));
+        let count = self.counts.entry(key).or_insert_with(|| {
+            self.order.push_back(key);
+            0
+        });
+        *count += 1;
+        let count = *count;
+
+        if self.counts.len() > MAX_SESSIONS
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.counts.remove(&oldest);
+        }
+        Some(count)
+    }
+}
+
+/// Runs the reflector on `listen` until interrupted.
+pub async fn serve(listen: SocketAddr) -> Result<()> {
+    let socket = UdpSocket::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    println!("pingx reflector listening on udp {}", socket.local_addr()?);
+
+    let mut sessions = Sessions::default();
+    let mut buf = vec![0u8; 65536];
+
+    loop {
+        let (n, peer) = tokio::select! {
+            result = socket.recv_from(&mut buf) => match result {
+                Ok(received) => received,
+                // ICMP errors about earlier replies
+                Err(_) => continue,
+            },
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+        let received = now_nanos();
+        let Some(count) = sessions.record(peer.ip(), &buf[..n]) else {
+            continue;
+        };
+
+        let packet = &mut buf[..n];
+        if !reflect(packet, received, count) {
+            continue;
+        }
+        packet[REQUEST_LEN + 8..REQUEST_LEN + 16].copy_from_slice(&now_nanos().to_be_bytes());
+        let _ = socket.send_to(packet, peer).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reflect() {
+        let mut packet = encode_request(7, 3, 1_000, 64);
+        assert_eq!(packet.len(), 64);
+        assert_eq!(encode_request(7, 3, 1_000, 0).len(), REPLY_LEN);
+        assert!(parse_reply(&packet).is_none());
+
+        assert!(reflect(&mut packet, 2_000, 1));
+        packet[REQUEST_LEN + 8..REQUEST_LEN + 16].copy_from_slice(&2_500u64.to_be_bytes());
+        assert_eq!(
+            parse_reply(&packet).unwrap(),
+            Reply {
+                session: 7,
+                seq: 3,
+                sent: 1_000,
+                received: 2_000,
+                reflected: 2_500,
+                count: 1,
+            }
+        );
+
+        // Replies are never reflected again
+        assert!(!reflect(&mut packet, 3_000, 2));
+        assert!(!reflect(&mut [0u8; REPLY_LEN], 3_000, 1));
+
+        // Only requests count towards their session
+        let peer = IpAddr::from([192, 0, 2, 1]);
+        let mut sessions = Sessions::default();
+        assert_eq!(sessions.record(peer, &encode_request(7, 1, 0, 0)), Some(1));
+        assert_eq!(sessions.record(peer, &packet), None);
+        assert_eq!(sessions.record(peer, &[0u8; REPLY_LEN]), None);
+        assert_eq!(
+            sessions.record(peer, &encode_request(7, 2, 0, 0)[..20]),
+            None
+        );
+        assert_eq!(sessions.record(peer, &encode_request(7, 3, 0, 0)), Some(2));
+        assert_eq!(sessions.counts.len(), 1);
+    }
+}
@@ -0,0 +1,184 @@
+//! Periodic re-resolution of target host names, so that long runs follow
+//! DNS-based failovers instead of probing the address found at startup.
+//!
+//! Addresses come from the system lookup, which also honours `/etc/hosts`.
+//! Their TTL is asked to the first nameserver of `/etc/resolv.conf`, with
+//! its search domains, and a name is not resolved again before its records
+//! expire.
+
+use crate::cli::Protocol;
+use crate::utils::{IpVersion, resolve_host};
+use anyhow::Result;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+
+/// Longest wait for the nameserver to tell the TTL of a name.
+const TTL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The address probed for a host name left its DNS records, and probes
+/// should move to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressChange {
+    pub host: String,
+    pub version: IpVersion,
+    pub from: IpAddr,
+    pub to: IpAddr,
+}
+
+/// What a `resolv.conf` says about where and how to look names up.
+#[derive(Debug, Default, PartialEq)]
+struct ResolvConf {
+    nameserver: Option<SocketAddr>,
+    search: Vec<String>,
+}
+
+/// The first nameserver of a `resolv.conf`, and its search domains, of
+/// which the last `search` or `domain` line decides.
+fn parse_resolv_conf(conf: &str) -> ResolvConf {
+    let mut resolv = ResolvConf::default();
+    for line in conf.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") if resolv.nameserver.is_none() => {
+                // Link-local nameservers may carry a zone, which has no use here
+                resolv.nameserver = words
+                    .next()
+                    .and_then(|addr| addr.split('%').next()?.parse::<IpAddr>().ok())
+                    .map(|addr| SocketAddr::new(addr, crate::pinger::dns::DEFAULT_PORT));
+            }
+            Some("search" | "domain") => resolv.search = words.map(str::to_string).collect(),
+            _ => {}
+        }
+    }
+    resolv
+}
+
+/// Names the system lookup tries for `host`, in order: names with a dot
+/// as they are first, short names with the search domains first.
+fn search_names(host: &str, search: &[String]) -> Vec<String> {
+    if host.ends_with('.') {
+        return vec![host.to_string()];
+    }
+    let searched = search.iter().map(|domain| format!("{}.{}", host, domain));
+    if host.contains('.') {
+        std::iter::once(host.to_string()).chain(searched).collect()
+    } else {
+        searched.chain(std::iter::once(host.to_string())).collect()
+    }
+}
+
+/// Addresses of `host`, and how long they may be cached when the nameserver
+/// returns the same ones. Without a TTL, for names the nameserver doesn't
+/// know, the caller's interval applies.
+pub async fn resolve(host: &str, version: IpVersion) -> Result<(Vec<IpAddr>, Option<Duration>)> {
+    let addrs = resolve_host(host, version).await?;
+
+    let qtypes: &[u16] = match version {
+        IpVersion::V4 => &[1],
+        IpVersion::V6 => &[28],
+        IpVersion::Any => &[28, 1],
+    };
+    let resolv = std::fs::read_to_string("/etc/resolv.conf")
+        .map(|conf| parse_resolv_conf(&conf))
+        .unwrap_or_default();
+    let Some(server) = resolv.nameserver else {
+        return Ok((addrs, None));
+    };
+    let ttl = tokio::time::timeout(TTL_TIMEOUT, async {
+        for name in search_names(host, &resolv.search) {
+            // Names overridden by /etc/hosts have no TTL
+            if let Ok((records, ttl)) = crate::pinger::dns::lookup(server, &name, qtypes).await
+                && records.iter().any(|addr| addrs.contains(addr))
+            {
+                return Some(ttl);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten();
+    Ok((addrs, ttl))
+}
+
+/// Resolves `host` again every `interval`, or once its records expire if
+/// that is later, and reports when `current` is no longer one of its
+/// addresses. The new address is picked among the others like the first
+/// one was. A failed resolution keeps the current address, which only the
+/// receiver moves once probes follow the change, so a change that could not
+/// be followed is reported again on the next pass.
+pub fn watch(
+    host: String,
+    protocol: Protocol,
+    version: IpVersion,
+    current: Arc<Mutex<IpAddr>>,
+    interval: Duration,
+    tx: mpsc::Sender<AddressChange>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut wait = interval;
+        loop {
+            tokio::time::sleep(wait).await;
+            wait = interval;
+            let Ok((addrs, ttl)) = resolve(&host, version).await else {
+                continue;
+            };
+            wait = wait.max(ttl.unwrap_or_default());
+            let from = *current.lock().await;
+            if addrs.contains(&from) {
+                continue;
+            }
+            let Ok(to) = crate::happy_eyeballs::select_best_addr(addrs, &protocol).await else {
+                continue;
+            };
+            let change = AddressChange {
+                host: host.clone(),
+                version,
+                from,
+                to,
+            };
+            if tx.send(change).await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let conf = "# generated\nsearch corp.example lab.example\nnameserver 10.0.0.53\nnameserver 1.1.1.1\n";
+        assert_eq!(
+            parse_resolv_conf(conf),
+            ResolvConf {
+                nameserver: Some("10.0.0.53:53".parse().unwrap()),
+                search: vec!["corp.example".to_string(), "lab.example".to_string()],
+            }
+        );
+        assert_eq!(
+            parse_resolv_conf("nameserver fe80::1%eth0").nameserver,
+            Some("[fe80::1]:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolv_conf("options ndots:2\n"),
+            ResolvConf::default()
+        );
+    }
+
+    #[test]
+    fn test_search_names() {
+        let search = ["corp.example".to_string()];
+        assert_eq!(search_names("db", &search), ["db.corp.example", "db"]);
+        assert_eq!(
+            search_names("db.eu", &search),
+            ["db.eu", "db.eu.corp.example"]
+        );
+        assert_eq!(search_names("db.eu.", &search), ["db.eu."]);
+    }
+}
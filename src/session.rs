@@ -1,17 +1,22 @@
 use crate::cli::Cli;
 use crate::pinger::Pinger;
+use crate::targets::{TargetSpec, Targets};
 use crate::utils::{IpVersion, resolve_host};
 use anyhow::Result;
 use colored::*;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use tokio::signal;
 
-pub use self::models::{PingResult, ProbeStatus};
+pub use self::models::{
+    DnsDetail, Download, GrpcDetail, HttpDetail, HttpTiming, NeighborDetail, NtpDetail, PingResult,
+    ProbeDetail, ProbeStatus, RedirectHop, ReflectorDetail, ServiceDetail, StunDetail, TcpDetail,
+    ValueCounts, WsDetail, WsHandshake,
+};
 
 mod models {
-    use std::net::IpAddr;
+    use std::net::{IpAddr, SocketAddr};
     use std::time::Duration;
     use std::time::Instant;
 
@@ -20,6 +25,228 @@ mod models {
         Success,
         Timeout,
         Error(String),
+        /// The target answered, but the response failed a user assertion.
+        AssertionFailed(String),
+        /// The TLS handshake failed, e.g. the certificate could not be verified.
+        TlsError(String),
+        /// The target answered, but reported that it cannot serve, e.g. a
+        /// gRPC service that is not serving or a resolver's SERVFAIL.
+        Unhealthy(String),
+        /// The target answered, but not as its protocol allows, or with an
+        /// error reply, e.g. a Redis `NOAUTH` error.
+        ProtocolError(String),
+    }
+
+    /// Protocol specific measurements attached to a probe result.
+    #[derive(Debug, Clone)]
+    pub enum ProbeDetail {
+        Http(HttpDetail),
+        Tcp(TcpDetail),
+        WebSocket(WsDetail),
+        Grpc(GrpcDetail),
+        Dns(DnsDetail),
+        Ntp(NtpDetail),
+        Reflector(ReflectorDetail),
+        Neighbor(NeighborDetail),
+        Service(ServiceDetail),
+        Stun(StunDetail),
+    }
+
+    /// Exchange with a Redis, memcached or PostgreSQL server, on a new
+    /// connection.
+    #[derive(Debug, Clone)]
+    pub struct ServiceDetail {
+        /// TCP connect, through the proxy if any.
+        pub connect: Duration,
+        /// SSLRequest and TLS handshake of PostgreSQL probes.
+        pub tls: Option<Duration>,
+        /// The server's answer, e.g. `PONG`, a version or the authentication
+        /// method asked for.
+        pub reply: String,
+    }
+
+    /// Answer to an ARP request or a neighbor solicitation.
+    #[derive(Debug, Clone)]
+    pub struct NeighborDetail {
+        pub mac: [u8; 6],
+        /// Other MAC addresses that answered the same request: several hosts
+        /// use the address.
+        pub duplicates: Vec<[u8; 6]>,
+        /// Other targets the same MAC address answered for.
+        pub shared_with: Vec<IpAddr>,
+    }
+
+    /// Server reflexive address reported by a STUN server.
+    #[derive(Debug, Clone, Copy)]
+    pub struct StunDetail {
+        pub mapped: SocketAddr,
+        /// The mapping of the previous answer, set when the NAT rebound.
+        pub previous: Option<SocketAddr>,
+    }
+
+    /// Parts of the round trip to a `pingx serve` reflector. One-way delays
+    /// are only meaningful when both clocks are synchronized.
+    #[derive(Debug, Clone, Copy)]
+    pub struct ReflectorDetail {
+        /// One-way delays to and from the reflector, in seconds.
+        pub forward: f64,
+        pub reverse: f64,
+        /// Time the reflector held the packet.
+        pub processing: Duration,
+        /// Packets of the session the reflector had received.
+        pub count: u64,
+    }
+
+    /// Packets lost on the way to the reflector and on the way back, known
+    /// up to the latest reply.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct DirectionalLoss {
+        pub forward_sent: u64,
+        pub forward_lost: u64,
+        pub reverse_sent: u64,
+        pub reverse_lost: u64,
+    }
+
+    /// Answer of an NTP server, computed from the four timestamps of the
+    /// exchange.
+    #[derive(Debug, Clone)]
+    pub struct NtpDetail {
+        /// Round-trip delay, without the time the server took to answer.
+        pub delay: Duration,
+        /// Offset of the server clock from the local clock, in seconds.
+        pub offset: f64,
+        pub stratum: u8,
+        /// Reference clock name or kiss code for stratum 0 and 1, otherwise
+        /// the IPv4 address (or IPv6 hash) of the upstream server.
+        pub refid: String,
+        pub leap: &'static str,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct DnsDetail {
+        pub rcode: &'static str,
+        /// Number of records in the answer section.
+        pub answers: u16,
+        /// The answer over UDP was truncated, and the query was repeated
+        /// over TCP.
+        pub truncated: bool,
+    }
+
+    /// Result of a gRPC health check call.
+    #[derive(Debug, Clone)]
+    pub struct GrpcDetail {
+        /// Serving status reported by the server, unset when the call failed.
+        pub serving: Option<&'static str>,
+        /// The HTTP/2 exchange that carried the call.
+        pub http: HttpDetail,
+    }
+
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct WsDetail {
+        /// Set on the probe that opened the connection.
+        pub handshake: Option<WsHandshake>,
+    }
+
+    /// Phases of opening a WebSocket connection, up to the `101 Switching
+    /// Protocols` answer to the upgrade request.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct WsHandshake {
+        pub dns: Duration,
+        pub proxy: Duration,
+        pub connect: Duration,
+        pub tls: Duration,
+        pub upgrade: Duration,
+    }
+
+    /// Phases of a TCP probe made through a proxy: reaching the proxy, then
+    /// the proxy opening the tunnel to the target.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct TcpDetail {
+        pub proxy: Duration,
+        pub connect: Duration,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct HttpDetail {
+        /// Protocol version negotiated with the server.
+        pub version: http::Version,
+        /// Whether the probe was sent on a kept-alive connection.
+        pub reused: bool,
+        pub timing: HttpTiming,
+        /// Every request of a followed redirect chain, ending with the final
+        /// response. Empty unless redirects are followed.
+        pub redirects: Vec<RedirectHop>,
+        /// Captured headers of the final response, in the order requested.
+        /// The value is `None` when the response did not carry the header.
+        pub headers: Vec<(String, Option<String>)>,
+        /// Body received from the final response in download mode.
+        pub download: Option<Download>,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Download {
+        pub bytes: u64,
+        /// Time from the response headers to the last byte read.
+        pub transfer: Duration,
+    }
+
+    impl Download {
+        /// Throughput in Mbit/s, unknown for empty or instant transfers.
+        pub fn mbps(&self) -> Option<f64> {
+            (self.bytes > 0 && !self.transfer.is_zero())
+                .then(|| self.bytes as f64 * 8.0 / self.transfer.as_secs_f64() / 1_000_000.0)
+        }
+    }
+
+    /// Distribution of the values of one response field, such as a captured
+    /// HTTP header or the rcode of DNS answers.
+    #[derive(Debug, Clone)]
+    pub struct ValueCounts {
+        pub name: String,
+        /// Each distinct value with the number of responses that carried
+        /// it, in order of appearance. `None` counts responses without it.
+        pub counts: Vec<(Option<String>, u64)>,
+    }
+
+    impl ValueCounts {
+        pub fn total(&self) -> u64 {
+            self.counts.iter().map(|(_, count)| count).sum()
+        }
+
+        fn record(&mut self, value: &Option<String>) {
+            match self.counts.iter_mut().find(|(v, _)| v == value) {
+                Some((_, count)) => *count += 1,
+                None => self.counts.push((value.clone(), 1)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct RedirectHop {
+        pub url: String,
+        pub status: u16,
+        pub latency: Duration,
+        /// The hop redirects from https to http.
+        pub downgrade: bool,
+        /// The hop redirects to a URL already visited in the chain.
+        pub looped: bool,
+    }
+
+    /// Duration of each phase of an HTTP request, in the order they happen.
+    /// Phases skipped by a reused connection are zero. HTTP/3 establishes
+    /// its connection with a single QUIC handshake instead of TCP and TLS.
+    /// Through a proxy, `proxy` is the connection to the proxy and `connect`
+    /// the tunnel it opens to the target.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct HttpTiming {
+        pub dns: Duration,
+        pub proxy: Duration,
+        pub connect: Duration,
+        pub tls: Duration,
+        pub quic: Duration,
+        pub ttfb: Duration,
+        pub transfer: Duration,
+        pub total: Duration,
     }
 
     #[derive(Debug, Clone)]
@@ -31,6 +258,7 @@ mod models {
         pub ttl: Option<u8>,
         pub rtt: Duration,
         pub status: ProbeStatus,
+        pub detail: Option<ProbeDetail>,
     }
 
     #[derive(Debug, Clone)]
@@ -41,6 +269,39 @@ mod models {
         pub received: u64,
         pub start_time: Instant,
         pub rtts: Vec<Duration>,
+        pub http_timings: Vec<HttpTiming>,
+        pub tcp_timings: Vec<TcpDetail>,
+        pub downloads: Vec<Download>,
+        pub ws_handshakes: Vec<WsHandshake>,
+        /// Distinct HTTP versions seen in responses, in order of appearance.
+        pub http_versions: Vec<http::Version>,
+        /// Successful HTTP probes sent on new and on reused connections.
+        pub new_connections: u64,
+        pub reused_connections: u64,
+        pub redirect_downgrades: u64,
+        pub redirect_loops: u64,
+        /// Captured header values of every HTTP response, including failed ones.
+        pub captured_headers: Vec<ValueCounts>,
+        /// Rcodes of every DNS answer, and the answers truncated over UDP.
+        pub dns_rcodes: ValueCounts,
+        pub dns_truncated: u64,
+        pub ntp_samples: Vec<NtpDetail>,
+        pub reflector_samples: Vec<ReflectorDetail>,
+        /// Sequence number and reflector count of the latest reply.
+        pub reflector_latest: Option<(u64, u64)>,
+        /// MAC addresses that answered ARP or NDP probes, and the probes
+        /// that revealed an address or MAC conflict.
+        pub neighbor_macs: ValueCounts,
+        pub duplicate_addresses: u64,
+        pub duplicate_macs: u64,
+        /// Reflexive addresses reported by STUN servers, and the answers
+        /// whose mapping differed from the previous one.
+        pub stun_mappings: ValueCounts,
+        pub mapping_changes: u64,
+        pub assertion_failures: u64,
+        pub tls_errors: u64,
+        pub unhealthy: u64,
+        pub protocol_errors: u64,
     }
 
     impl PingStats {
@@ -52,14 +313,144 @@ mod models {
                 received: 0,
                 start_time: Instant::now(),
                 rtts: Vec::new(),
+                http_timings: Vec::new(),
+                tcp_timings: Vec::new(),
+                downloads: Vec::new(),
+                ws_handshakes: Vec::new(),
+                http_versions: Vec::new(),
+                new_connections: 0,
+                reused_connections: 0,
+                redirect_downgrades: 0,
+                redirect_loops: 0,
+                captured_headers: Vec::new(),
+                dns_rcodes: ValueCounts {
+                    name: "rcode".to_string(),
+                    counts: Vec::new(),
+                },
+                dns_truncated: 0,
+                ntp_samples: Vec::new(),
+                reflector_samples: Vec::new(),
+                reflector_latest: None,
+                neighbor_macs: ValueCounts {
+                    name: "mac".to_string(),
+                    counts: Vec::new(),
+                },
+                duplicate_addresses: 0,
+                duplicate_macs: 0,
+                stun_mappings: ValueCounts {
+                    name: "mapping".to_string(),
+                    counts: Vec::new(),
+                },
+                mapping_changes: 0,
+                assertion_failures: 0,
+                tls_errors: 0,
+                unhealthy: 0,
+                protocol_errors: 0,
+            }
+        }
+
+        /// Probe counts and round trips of several pingers of a target taken
+        /// together, timed from the first to start.
+        pub fn combined(target: String, all: &[&PingStats]) -> Self {
+            let mut combined = Self::new(target, IpAddr::from([0, 0, 0, 0]));
+            for stats in all {
+                combined.transmitted += stats.transmitted;
+                combined.received += stats.received;
+                combined.rtts.extend(&stats.rtts);
+                combined.start_time = combined.start_time.min(stats.start_time);
             }
+            combined
+        }
+
+        /// Splits the loss of reflector probes by direction. Up to the latest
+        /// reply, the reflector received `count` of the `seq` probes sent,
+        /// and sent as many replies.
+        pub fn directional_loss(&self) -> Option<DirectionalLoss> {
+            let (seq, count) = self.reflector_latest?;
+            Some(DirectionalLoss {
+                forward_sent: seq,
+                forward_lost: seq.saturating_sub(count),
+                reverse_sent: count,
+                reverse_lost: count.saturating_sub(self.reflector_samples.len() as u64),
+            })
         }
 
         pub fn update(&mut self, result: &PingResult) {
             self.transmitted += 1;
+            if let Some(ProbeDetail::Http(detail)) = &result.detail {
+                for hop in &detail.redirects {
+                    self.redirect_downgrades += hop.downgrade as u64;
+                    self.redirect_loops += hop.looped as u64;
+                }
+                for (name, value) in &detail.headers {
+                    let index = match self.captured_headers.iter().position(|h| &h.name == name) {
+                        Some(index) => index,
+                        None => {
+                            self.captured_headers.push(ValueCounts {
+                                name: name.clone(),
+                                counts: Vec::new(),
+                            });
+                            self.captured_headers.len() - 1
+                        }
+                    };
+                    self.captured_headers[index].record(value);
+                }
+            }
+            if let Some(ProbeDetail::Dns(detail)) = &result.detail {
+                self.dns_rcodes.record(&Some(detail.rcode.to_string()));
+                self.dns_truncated += detail.truncated as u64;
+            }
             if let ProbeStatus::Success = result.status {
                 self.received += 1;
                 self.rtts.push(result.rtt);
+                match &result.detail {
+                    Some(ProbeDetail::Http(detail))
+                    | Some(ProbeDetail::Grpc(GrpcDetail { http: detail, .. })) => {
+                        self.http_timings.push(detail.timing);
+                        if detail.reused {
+                            self.reused_connections += 1;
+                        } else {
+                            self.new_connections += 1;
+                        }
+                        if !self.http_versions.contains(&detail.version) {
+                            self.http_versions.push(detail.version);
+                        }
+                        self.downloads.extend(detail.download);
+                    }
+                    Some(ProbeDetail::Tcp(detail)) => self.tcp_timings.push(*detail),
+                    Some(ProbeDetail::WebSocket(detail)) => {
+                        self.ws_handshakes.extend(detail.handshake)
+                    }
+                    Some(ProbeDetail::Ntp(detail)) => self.ntp_samples.push(detail.clone()),
+                    Some(ProbeDetail::Reflector(detail)) => {
+                        self.reflector_samples.push(*detail);
+                        if self
+                            .reflector_latest
+                            .is_none_or(|(seq, _)| seq < result.seq)
+                        {
+                            self.reflector_latest = Some((result.seq, detail.count));
+                        }
+                    }
+                    Some(ProbeDetail::Neighbor(detail)) => {
+                        self.neighbor_macs
+                            .record(&Some(crate::session::Session::format_mac(&detail.mac)));
+                        self.duplicate_addresses += !detail.duplicates.is_empty() as u64;
+                        self.duplicate_macs += !detail.shared_with.is_empty() as u64;
+                    }
+                    Some(ProbeDetail::Stun(detail)) => {
+                        self.stun_mappings.record(&Some(detail.mapped.to_string()));
+                        self.mapping_changes += detail.previous.is_some() as u64;
+                    }
+                    Some(ProbeDetail::Dns(_)) | Some(ProbeDetail::Service(_)) | None => {}
+                }
+            } else if let ProbeStatus::AssertionFailed(_) = result.status {
+                self.assertion_failures += 1;
+            } else if let ProbeStatus::TlsError(_) = result.status {
+                self.tls_errors += 1;
+            } else if let ProbeStatus::Unhealthy(_) = result.status {
+                self.unhealthy += 1;
+            } else if let ProbeStatus::ProtocolError(_) = result.status {
+                self.protocol_errors += 1;
             }
         }
     }
@@ -72,6 +463,8 @@ pub struct JsonResult {
     pub target: String,
     pub protocol: String,
     pub ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
     pub packet_size: usize,
     pub ttl: u32,
     pub sent: u64,
@@ -83,91 +476,779 @@ pub struct JsonResult {
     pub max: f64,
     pub mdev: f64,
     pub jitter: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assertion_failures: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_errors: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unhealthy: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol_errors: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_versions: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_connections: Option<JsonConnections>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_timing: Option<JsonHttpTiming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<JsonDownload>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_timing: Option<JsonTcpTiming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ws_handshake: Option<JsonWsHandshake>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_downgrades: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_loops: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Vec<JsonValueCounts>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<JsonDns>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntp: Option<JsonNtp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reflector: Option<JsonReflector>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub neighbor: Option<JsonNeighbor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stun: Option<JsonStun>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_changes: Option<Vec<JsonAddressChange>>,
 }
 
-pub struct Session {
-    cli: Cli,
+/// An address change of a target resolved again with `--re-resolve`, at a
+/// time in milliseconds since the session started.
+#[derive(Serialize)]
+pub struct JsonAddressChange {
+    pub from: String,
+    pub to: String,
+    pub time: f64,
 }
 
-impl Session {
-    pub fn new(cli: Cli) -> Self {
-        Self { cli }
+/// Reflexive addresses reported by STUN servers, and the number of answers
+/// whose mapping changed.
+#[derive(Serialize)]
+pub struct JsonStun {
+    pub mappings: Vec<JsonValueCount>,
+    pub changes: u64,
+}
+
+impl JsonStun {
+    fn from_stats(stats: &models::PingStats) -> Option<Self> {
+        if stats.stun_mappings.counts.is_empty() {
+            return None;
+        }
+        Some(Self {
+            mappings: JsonValueCount::distribution(&stats.stun_mappings),
+            changes: stats.mapping_changes,
+        })
     }
+}
 
-    pub async fn run(&self) -> Result<()> {
-        let targets = &self.cli.targets;
-        let multi_target = targets.len() > 1;
-        let quiet = self.cli.quiet;
+/// MAC addresses that answered ARP or NDP probes, and the probes that
+/// revealed an address or MAC conflict.
+#[derive(Serialize)]
+pub struct JsonNeighbor {
+    pub macs: Vec<JsonValueCount>,
+    pub duplicate_addresses: u64,
+    pub duplicate_macs: u64,
+}
 
-        let headers = crate::utils::parse_headers(&self.cli.headers)?;
+impl JsonNeighbor {
+    fn from_stats(stats: &models::PingStats) -> Option<Self> {
+        if stats.neighbor_macs.counts.is_empty() {
+            return None;
+        }
+        Some(Self {
+            macs: JsonValueCount::distribution(&stats.neighbor_macs),
+            duplicate_addresses: stats.duplicate_addresses,
+            duplicate_macs: stats.duplicate_macs,
+        })
+    }
+}
 
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<models::PingResult>(100);
+/// One-way delays and processing time of reflector probes in milliseconds,
+/// and the loss in each direction.
+#[derive(Serialize)]
+pub struct JsonReflector {
+    pub forward: JsonPhase,
+    pub reverse: JsonPhase,
+    pub processing: JsonPhase,
+    pub forward_lost: u64,
+    pub reverse_lost: u64,
+}
+
+impl JsonReflector {
+    fn from_stats(stats: &models::PingStats) -> Option<Self> {
+        let loss = stats.directional_loss()?;
+        let samples = &stats.reflector_samples;
+        Some(Self {
+            forward: JsonPhase::from_values(samples.iter().map(|s| s.forward * 1000.0).collect()),
+            reverse: JsonPhase::from_values(samples.iter().map(|s| s.reverse * 1000.0).collect()),
+            processing: JsonPhase::from_durations(samples.iter().map(|s| s.processing)),
+            forward_lost: loss.forward_lost,
+            reverse_lost: loss.reverse_lost,
+        })
+    }
+}
 
-        let mut all_stats: HashMap<String, models::PingStats> = HashMap::new();
-        let mut target_protocols: HashMap<String, crate::cli::Protocol> = HashMap::new();
-        let mut pingers: Vec<Box<dyn Pinger>> = Vec::new();
+/// Clock offset and delay of successful NTP probes, in milliseconds, with
+/// the stratum of the last answer.
+#[derive(Serialize)]
+pub struct JsonNtp {
+    pub offset: JsonPhase,
+    pub delay: JsonPhase,
+    pub stratum: u8,
+}
 
-        let mut client_v4: Option<Arc<crate::pinger::icmp::IcmpClient>> = None;
-        let mut client_v6: Option<Arc<crate::pinger::icmp::IcmpClient>> = None;
+impl JsonNtp {
+    fn from_samples(samples: &[NtpDetail]) -> Option<Self> {
+        let last = samples.last()?;
+        Some(Self {
+            offset: JsonPhase::from_values(samples.iter().map(|s| s.offset * 1000.0).collect()),
+            delay: JsonPhase::from_durations(samples.iter().map(|s| s.delay)),
+            stratum: last.stratum,
+        })
+    }
+}
 
-        for target_string in targets {
-            // Detect protocol and host
-            let (protocol, host_to_resolve) =
-                match crate::utils::detect_protocol(&self.cli, target_string) {
-                    Ok(res) => res,
-                    Err(e) => {
-                        eprintln!("pingx: {}: {}", target_string, e);
-                        if !multi_target {
-                            return Err(e);
-                        }
-                        continue;
-                    }
-                };
+/// Rcodes of the answers to DNS probes, and how many were truncated over UDP.
+#[derive(Serialize)]
+pub struct JsonDns {
+    pub rcodes: Vec<JsonValueCount>,
+    pub truncated: u64,
+}
+
+/// Distribution of the values of one response field, such as a captured
+/// header, most frequent value first.
+#[derive(Serialize)]
+pub struct JsonValueCounts {
+    pub name: String,
+    pub values: Vec<JsonValueCount>,
+}
+
+#[derive(Serialize)]
+pub struct JsonValueCount {
+    /// `null` for responses without the field.
+    pub value: Option<String>,
+    pub count: u64,
+    pub percent: f64,
+}
+
+impl JsonValueCounts {
+    fn from_stats(headers: &[ValueCounts]) -> Option<Vec<Self>> {
+        if headers.is_empty() {
+            return None;
+        }
+        Some(
+            headers
+                .iter()
+                .map(|header| Self {
+                    name: header.name.clone(),
+                    values: JsonValueCount::distribution(header),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl JsonValueCount {
+    fn distribution(field: &ValueCounts) -> Vec<Self> {
+        Session::value_distribution(field)
+            .into_iter()
+            .map(|(value, count, percent)| Self {
+                value: value.clone(),
+                count,
+                percent: (percent * 1000.0).round() / 1000.0,
+            })
+            .collect()
+    }
+}
+
+impl JsonDns {
+    fn from_stats(stats: &models::PingStats) -> Option<Self> {
+        if stats.dns_rcodes.counts.is_empty() {
+            return None;
+        }
+        Some(Self {
+            rcodes: JsonValueCount::distribution(&stats.dns_rcodes),
+            truncated: stats.dns_truncated,
+        })
+    }
+}
+
+/// Number of successful HTTP probes sent on new and on reused connections.
+#[derive(Serialize)]
+pub struct JsonConnections {
+    pub new: u64,
+    pub reused: u64,
+}
+
+/// Per-phase statistics of successful HTTP probes, in milliseconds.
+#[derive(Serialize)]
+pub struct JsonHttpTiming {
+    pub dns: JsonPhase,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<JsonPhase>,
+    pub connect: JsonPhase,
+    pub tls: JsonPhase,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quic: Option<JsonPhase>,
+    pub ttfb: JsonPhase,
+    pub transfer: JsonPhase,
+    pub total: JsonPhase,
+}
+
+#[derive(Serialize)]
+pub struct JsonPhase {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+impl JsonPhase {
+    /// Summarizes a non-empty set of durations.
+    fn from_durations(durations: impl Iterator<Item = Duration>) -> Self {
+        Self::from_values(durations.map(|d| d.as_secs_f64() * 1000.0).collect())
+    }
+
+    /// Summarizes a non-empty set of values.
+    fn from_values(values: Vec<f64>) -> Self {
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let avg = values.iter().sum::<f64>() / values.len() as f64;
+        JsonPhase {
+            min: (min * 1000.0).round() / 1000.0,
+            avg: (avg * 1000.0).round() / 1000.0,
+            max: (max * 1000.0).round() / 1000.0,
+        }
+    }
+}
+
+/// Statistics of the bodies downloaded by successful HTTP probes.
+#[derive(Serialize)]
+pub struct JsonDownload {
+    /// Bytes received per probe.
+    pub bytes: JsonPhase,
+    /// Throughput in Mbit/s.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mbps: Option<JsonPhase>,
+}
+
+impl JsonDownload {
+    fn from_downloads(downloads: &[Download]) -> Option<Self> {
+        if downloads.is_empty() {
+            return None;
+        }
+        let rates: Vec<f64> = downloads.iter().filter_map(Download::mbps).collect();
+        Some(Self {
+            bytes: JsonPhase::from_values(downloads.iter().map(|d| d.bytes as f64).collect()),
+            mbps: (!rates.is_empty()).then(|| JsonPhase::from_values(rates)),
+        })
+    }
+}
+
+/// Statistics of the WebSocket connections opened by successful probes, in
+/// milliseconds.
+#[derive(Serialize)]
+pub struct JsonWsHandshake {
+    pub connections: usize,
+    pub dns: JsonPhase,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<JsonPhase>,
+    pub connect: JsonPhase,
+    pub tls: JsonPhase,
+    pub upgrade: JsonPhase,
+}
 
-            target_protocols.insert(target_string.clone(), protocol.clone());
+impl JsonWsHandshake {
+    fn from_handshakes(handshakes: &[WsHandshake]) -> Option<Self> {
+        if handshakes.is_empty() {
+            return None;
+        }
+        let phase =
+            |f: fn(&WsHandshake) -> Duration| JsonPhase::from_durations(handshakes.iter().map(f));
+        Some(Self {
+            connections: handshakes.len(),
+            dns: phase(|h| h.dns),
+            proxy: handshakes
+                .iter()
+                .any(|h| !h.proxy.is_zero())
+                .then(|| phase(|h| h.proxy)),
+            connect: phase(|h| h.connect),
+            tls: phase(|h| h.tls),
+            upgrade: phase(|h| h.upgrade),
+        })
+    }
+}
+
+/// Statistics of successful TCP probes made through a proxy, in milliseconds.
+#[derive(Serialize)]
+pub struct JsonTcpTiming {
+    pub proxy: JsonPhase,
+    pub connect: JsonPhase,
+}
+
+impl JsonTcpTiming {
+    fn from_details(details: &[TcpDetail]) -> Option<Self> {
+        if details.is_empty() {
+            return None;
+        }
+        Some(Self {
+            proxy: JsonPhase::from_durations(details.iter().map(|d| d.proxy)),
+            connect: JsonPhase::from_durations(details.iter().map(|d| d.connect)),
+        })
+    }
+}
 
-            let ip_version = if self.cli.ipv4 {
+impl JsonHttpTiming {
+    fn from_timings(timings: &[HttpTiming]) -> Option<Self> {
+        if timings.is_empty() {
+            return None;
+        }
+
+        let phase =
+            |f: fn(&HttpTiming) -> Duration| JsonPhase::from_durations(timings.iter().map(f));
+
+        Some(Self {
+            dns: phase(|t| t.dns),
+            proxy: timings
+                .iter()
+                .any(|t| !t.proxy.is_zero())
+                .then(|| phase(|t| t.proxy)),
+            connect: phase(|t| t.connect),
+            tls: phase(|t| t.tls),
+            quic: timings
+                .iter()
+                .any(|t| !t.quic.is_zero())
+                .then(|| phase(|t| t.quic)),
+            ttfb: phase(|t| t.ttfb),
+            transfer: phase(|t| t.transfer),
+            total: phase(|t| t.total),
+        })
+    }
+}
+
+/// Probes sent to each target of a sweep without `-c`.
+const SWEEP_COUNT: u64 = 3;
+
+/// Probes per second of a sweep without `--rate`.
+const SWEEP_RATE: u32 = 100;
+
+/// Under a rate limit of one probe per `gap`, whether a probe may go out
+/// `now` when the next was due at `next`, and if so when the one after may.
+/// Time lost to timer resolution is caught up, but not idle time.
+fn rate_slot(
+    next: tokio::time::Instant,
+    now: tokio::time::Instant,
+    gap: Duration,
+) -> Option<tokio::time::Instant> {
+    if next > now {
+        return None;
+    }
+    let behind = now.saturating_duration_since(next) > gap;
+    Some(if behind { now } else { next } + gap)
+}
+
+/// Targets of a sweep with their statistics.
+type SweepTargets<'a> = Vec<(&'a String, &'a models::PingStats)>;
+
+/// When a pinger sends its probes. Targets from a targets file may each have
+/// their own interval and count.
+struct Schedule {
+    name: String,
+    pinger: Box<dyn Pinger>,
+    interval: Duration,
+    count: Option<u64>,
+    timeout: Duration,
+    seq: u64,
+    next_at: tokio::time::Instant,
+    /// The tick after the last probe has passed.
+    finished: bool,
+}
+
+/// Pingers of a session and what is known of their targets, which grows as
+/// targets are read.
+struct Probes {
+    tx: tokio::sync::mpsc::Sender<models::PingResult>,
+    http: crate::pinger::http::HttpOptions,
+    proxy: Option<Arc<crate::pinger::proxy::Proxy>>,
+    ttl: u32,
+    /// Whether a failing target is reported and skipped instead of ending
+    /// the session.
+    multi_target: bool,
+    sweep: bool,
+    all_stats: HashMap<String, models::PingStats>,
+    target_protocols: HashMap<String, crate::cli::Protocol>,
+    target_sizes: HashMap<String, usize>,
+    labelled_targets: HashSet<String>,
+    /// Targets in input order, each with the names of the pingers it expanded into
+    groups: Vec<(String, Vec<String>)>,
+    /// Pinger name -> owning target, only for targets that expanded into several pingers
+    target_groups: HashMap<String, String>,
+    schedules: Vec<Schedule>,
+    client_v4: Option<Arc<crate::pinger::icmp::IcmpClient>>,
+    client_v6: Option<Arc<crate::pinger::icmp::IcmpClient>>,
+    /// Certificate settings, loaded into `http` once a target needs TLS, so
+    /// that runs without one don't fail on them
+    pending_tls: Option<crate::pinger::http::TlsOptions>,
+    changes_tx: tokio::sync::mpsc::Sender<crate::resolver::AddressChange>,
+    /// Pinger name -> how to create it again, for targets resolved again
+    retargets: HashMap<String, Retarget>,
+    /// One resolver task per host name, whatever the number of its targets
+    watchers: HashMap<(String, IpVersion), Watcher>,
+    /// Targets whose address changed, from then on with stats per address
+    /// under `name@addr`
+    split_targets: HashSet<String>,
+    /// Target name -> address changes, with the time since the session started
+    address_changes: HashMap<String, Vec<(std::net::IpAddr, std::net::IpAddr, Duration)>>,
+    start: tokio::time::Instant,
+}
+
+/// What it takes to create the pinger of a target again for another address.
+struct Retarget {
+    host: String,
+    protocol: crate::cli::Protocol,
+    config: crate::pinger::PingerConfig,
+    addr: std::net::IpAddr,
+}
+
+/// The resolver task of a host name, and the targets that probe it.
+struct Watcher {
+    task: tokio::task::JoinHandle<()>,
+    /// Address the task compares the records with
+    current: Arc<tokio::sync::Mutex<std::net::IpAddr>>,
+    members: Vec<String>,
+}
+
+impl Probes {
+    /// Loads the certificate settings of the command line into the TLS
+    /// configuration of the probes, the first time a target needs it.
+    fn load_tls(&mut self) -> Result<()> {
+        let Some(options) = &self.pending_tls else {
+            return Ok(());
+        };
+        let mut tls_config = crate::pinger::http::tls::client_config(options)?;
+        tls_config.alpn_protocols = self.http.version.alpn_protocols();
+        if !self.http.reuse {
+            // Fresh connections must also pay for a full TLS handshake
+            tls_config.resumption = rustls::client::Resumption::disabled();
+        }
+        self.http.tls = Arc::new(tls_config);
+        self.pending_tls = None;
+        Ok(())
+    }
+
+    /// Moves the targets of a host name to the address it changed to, and
+    /// tells whether any moved. A target that fails to move keeps probing
+    /// its address, and the change is reported again until all have moved.
+    async fn follow(&mut self, change: &crate::resolver::AddressChange) -> bool {
+        let Some(watcher) = self.watchers.get(&(change.host.clone(), change.version)) else {
+            return false;
+        };
+        let (members, current) = (watcher.members.clone(), watcher.current.clone());
+        let mut moved = false;
+        let mut failed = false;
+        for member in &members {
+            match self.retarget(member, change.to).await {
+                Ok(retargeted) => moved |= retargeted,
+                Err(e) => {
+                    failed = true;
+                    eprintln!(
+                        "pingx: {}: failed to follow the address change to {}: {}",
+                        member, change.to, e
+                    );
+                }
+            }
+        }
+        if !failed {
+            *current.lock().await = change.to;
+        }
+        moved
+    }
+
+    /// Moves the pinger of a target to another address, and tells whether it
+    /// moved. From the first change on, the target has its stats per
+    /// address, like with `--all-addrs`.
+    async fn retarget(&mut self, name: &str, to: std::net::IpAddr) -> Result<bool> {
+        let Some(retarget) = self.retargets.get(name) else {
+            return Ok(false);
+        };
+        let from = retarget.addr;
+        if from == to {
+            return Ok(false);
+        }
+        let (host, protocol, config) = (
+            retarget.host.clone(),
+            retarget.protocol.clone(),
+            retarget.config.clone(),
+        );
+        if protocol == crate::cli::Protocol::Icmp {
+            let (client, v6) = if to.is_ipv4() {
+                (&mut self.client_v4, false)
+            } else {
+                (&mut self.client_v6, true)
+            };
+            if client.is_none() {
+                *client = Some(Arc::new(crate::pinger::icmp::IcmpClient::new(
+                    v6, self.ttl,
+                )?));
+            }
+        }
+        let mut pinger = crate::pinger::create_pinger(
+            name.to_string(),
+            host,
+            protocol,
+            to,
+            config,
+            self.client_v4.clone(),
+            self.client_v6.clone(),
+        )?;
+        pinger.start(self.tx.clone()).await?;
+        if let Some(schedule) = self.schedules.iter_mut().find(|s| s.name == name) {
+            let mut previous = std::mem::replace(&mut schedule.pinger, pinger);
+            previous.stop().await.ok();
+        }
+        if let Some(retarget) = self.retargets.get_mut(name) {
+            retarget.addr = to;
+        }
+
+        self.split_stats(name, from, to);
+        self.address_changes
+            .entry(name.to_string())
+            .or_default()
+            .push((from, to, self.start.elapsed()));
+        Ok(true)
+    }
+
+    /// Gives a target that changed address a member per address in its
+    /// group, the stats so far going to the address it changed from.
+    fn split_stats(&mut self, name: &str, from: std::net::IpAddr, to: std::net::IpAddr) {
+        let prefix = format!("{}@", name);
+        let Some((index, mut position)) =
+            self.groups
+                .iter()
+                .enumerate()
+                .find_map(|(i, (_, members))| {
+                    members
+                        .iter()
+                        .rposition(|m| m == name || m.starts_with(&prefix))
+                        .map(|position| (i, position))
+                })
+        else {
+            return;
+        };
+        let group = self.groups[index].0.clone();
+
+        let mut members = Vec::new();
+        if self.split_targets.insert(name.to_string()) {
+            self.groups[index].1.remove(position);
+            let stats = self.all_stats.remove(name);
+            members.push((format!("{}{}", prefix, from), from, stats));
+        } else {
+            position += 1;
+        }
+        let member = format!("{}{}", prefix, to);
+        if !self.all_stats.contains_key(&member) {
+            members.push((member, to, None));
+        }
+
+        for (member, addr, stats) in members {
+            let stats = stats.unwrap_or_else(|| models::PingStats::new(member.clone(), addr));
+            if let Some(protocol) = self.target_protocols.get(name).cloned() {
+                self.target_protocols.insert(member.clone(), protocol);
+            }
+            if let Some(size) = self.target_sizes.get(name).copied() {
+                self.target_sizes.insert(member.clone(), size);
+            }
+            self.all_stats.insert(member.clone(), stats);
+            self.target_groups.insert(member.clone(), group.clone());
+            self.groups[index].1.insert(position, member);
+            position += 1;
+        }
+    }
+
+    /// Stats key of a result: the pinger name, or its member for the probed
+    /// address once the target changed address.
+    fn result_key(&self, result: &models::PingResult) -> String {
+        if self.split_targets.contains(&result.target) {
+            format!("{}@{}", result.target, result.target_addr)
+        } else {
+            result.target.clone()
+        }
+    }
+
+    /// Sets up a target, or each address of a network or range target.
+    async fn add(&mut self, target: &TargetSpec) -> Result<()> {
+        let Some(addrs) = crate::targets::expand_range(&target.target) else {
+            return self.add_target(target).await;
+        };
+        let addrs = addrs.and_then(|addrs| match target.label {
+            Some(_) => Err(anyhow::anyhow!("A network or range cannot have a label")),
+            None => Ok(addrs),
+        });
+        let addrs = match addrs {
+            Ok(addrs) => addrs,
+            Err(e) => {
+                eprintln!("pingx: {}: {}", target.target, e);
+                if !self.multi_target {
+                    return Err(e);
+                }
+                return Ok(());
+            }
+        };
+
+        println!("PING {} ({} addresses)", target.target, addrs.len());
+        // Like port lists, a range is reported per address
+        self.multi_target = true;
+        for addr in addrs {
+            let spec = TargetSpec {
+                target: addr.to_string(),
+                label: None,
+                cli: target.cli.clone(),
+            };
+            self.add_target(&spec).await?;
+        }
+        Ok(())
+    }
+
+    /// Sets up the pingers of a target and schedules their first probe.
+    /// Errors only abort a session of a single target.
+    async fn add_target(&mut self, target: &TargetSpec) -> Result<()> {
+        let cli = &target.cli;
+        let target_string = &target.target;
+        // Detect protocol and host, expanding port lists into one entry per port
+        let mut specs = match crate::utils::expand_target(cli, target_string) {
+            Ok(specs) => specs,
+            Err(e) => {
+                eprintln!("pingx: {}: {}", target_string, e);
+                if !self.multi_target {
+                    return Err(e);
+                }
+                return Ok(());
+            }
+        };
+        let tls = if specs.iter().any(|(_, protocol, _)| protocol.uses_tls()) {
+            self.load_tls()
+        } else {
+            Ok(())
+        };
+        let (headers, http_options) = match tls.and_then(|()| Session::target_http(cli, &self.http))
+        {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("pingx: {}: {}", target_string, e);
+                if !self.multi_target {
+                    return Err(e);
+                }
+                return Ok(());
+            }
+        };
+        let multi_target = self.multi_target || specs.len() > 1;
+        if let Some(label) = &target.label {
+            self.labelled_targets.insert(label.clone());
+            let expanded = specs.len() > 1;
+            for (name, _, _) in &mut specs {
+                *name = match name.rsplit_once(':') {
+                    Some((_, port)) if expanded => format!("{}:{}", label, port),
+                    _ => label.clone(),
+                };
+            }
+        }
+        // Members of a port list are named `host:port`, so the group is labelled by the host
+        let group = match specs.as_slice() {
+            [(first, _, _), _, ..] => first
+                .rsplit_once(':')
+                .map_or(target_string.as_str(), |(host, _)| host)
+                .to_string(),
+            _ => target.label.as_ref().unwrap_or(target_string).clone(),
+        };
+        let mut members = Vec::new();
+
+        for (name, protocol, host_to_resolve) in specs {
+            let ip_version = if cli.ipv4 || cli.arp {
                 IpVersion::V4
-            } else if self.cli.ipv6 {
+            } else if cli.ipv6 || cli.ndp {
                 IpVersion::V6
             } else {
                 IpVersion::Any
             };
 
-            match resolve_host(&host_to_resolve, ip_version).await {
-                Ok(addrs) => {
-                    let target_addr =
-                        match crate::happy_eyeballs::select_best_addr(addrs, &protocol).await {
-                            Ok(addr) => addr,
-                            Err(e) => {
-                                eprintln!("pingx: {}: {}", target_string, e);
-                                if !multi_target {
-                                    return Err(e);
-                                }
-                                continue;
-                            }
-                        };
+            let proxied = match (&self.proxy, &protocol) {
+                (None, _) => false,
+                (
+                    Some(_),
+                    crate::cli::Protocol::Icmp
+                    | crate::cli::Protocol::Dns(_)
+                    | crate::cli::Protocol::Udp(_)
+                    | crate::cli::Protocol::Ntp(_)
+                    | crate::cli::Protocol::Stun(_)
+                    | crate::cli::Protocol::Reflector(_)
+                    | crate::cli::Protocol::Arp
+                    | crate::cli::Protocol::Ndp
+                    | crate::cli::Protocol::Unix(_),
+                ) => {
+                    let kind = match protocol {
+                        crate::cli::Protocol::Icmp => "ICMP",
+                        crate::cli::Protocol::Arp => "ARP",
+                        crate::cli::Protocol::Ndp => "NDP",
+                        crate::cli::Protocol::Dns(_) => "DNS",
+                        crate::cli::Protocol::Ntp(_) => "NTP",
+                        crate::cli::Protocol::Stun(_) => "STUN",
+                        crate::cli::Protocol::Reflector(_) => "Reflector",
+                        crate::cli::Protocol::Unix(_) => "Unix socket",
+                        _ => "UDP",
+                    };
+                    let e = anyhow::anyhow!("{} probes cannot use a proxy", kind);
+                    eprintln!("pingx: {}: {}", name, e);
+                    if !multi_target {
+                        return Err(e);
+                    }
+                    continue;
+                }
+                (Some(_), _) => true,
+            };
 
-                    // Initialize ICMP client if needed
-                    if let crate::cli::Protocol::Icmp = protocol {
-                        if target_addr.is_ipv4() {
-                            if client_v4.is_none() {
-                                match crate::pinger::icmp::IcmpClient::new(false, self.cli.ttl) {
-                                    Ok(c) => client_v4 = Some(Arc::new(c)),
-                                    Err(e) => {
-                                        eprintln!("Failed to create IPv4 ICMP client: {}", e);
-                                        if !multi_target {
-                                            return Err(anyhow::anyhow!(e));
-                                        }
-                                        continue;
-                                    }
-                                }
-                            }
-                        } else if client_v6.is_none() {
-                            match crate::pinger::icmp::IcmpClient::new(true, self.cli.ttl) {
-                                Ok(c) => client_v6 = Some(Arc::new(c)),
+            let addrs = match Session::select_target_addrs(
+                &host_to_resolve,
+                &protocol,
+                ip_version,
+                proxied,
+                cli.all_addrs,
+            )
+            .await
+            {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    eprintln!("pingx: {}: {}", name, e);
+                    if !multi_target {
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+            // With --all-addrs, every address of the host gets a pinger of its
+            // own, named `name@addr`
+            let several = addrs.len() > 1;
+            let multi_target = multi_target || several;
+            let base = name;
+            for target_addr in addrs {
+                let name = if several {
+                    format!("{}@{}", base, target_addr)
+                } else {
+                    base.clone()
+                };
+                self.target_protocols.insert(name.clone(), protocol.clone());
+                self.target_sizes.insert(name.clone(), cli.size);
+
+                // Initialize ICMP client if needed
+                if let crate::cli::Protocol::Icmp = protocol {
+                    if target_addr.is_ipv4() {
+                        if self.client_v4.is_none() {
+                            match crate::pinger::icmp::IcmpClient::new(false, self.ttl) {
+                                Ok(c) => self.client_v4 = Some(Arc::new(c)),
                                 Err(e) => {
-                                    eprintln!("Failed to create IPv6 ICMP client: {}", e);
+                                    eprintln!("Failed to create IPv4 ICMP client: {}", e);
                                     if !multi_target {
                                         return Err(anyhow::anyhow!(e));
                                     }
@@ -175,61 +1256,261 @@ impl Session {
                                 }
                             }
                         }
+                    } else if self.client_v6.is_none() {
+                        match crate::pinger::icmp::IcmpClient::new(true, self.ttl) {
+                            Ok(c) => self.client_v6 = Some(Arc::new(c)),
+                            Err(e) => {
+                                eprintln!("Failed to create IPv6 ICMP client: {}", e);
+                                if !multi_target {
+                                    return Err(anyhow::anyhow!(e));
+                                }
+                                continue;
+                            }
+                        }
                     }
+                }
 
-                    all_stats.insert(
-                        target_string.clone(),
-                        models::PingStats::new(target_string.clone(), target_addr),
-                    );
+                self.all_stats.insert(
+                    name.clone(),
+                    models::PingStats::new(name.clone(), target_addr),
+                );
 
-                    println!(
-                        "PING {} ({}) {}({}) bytes of data.",
-                        target_string,
-                        target_addr,
-                        self.cli.size,
-                        self.cli.size + 28
-                    );
+                // Sweeps report their targets together at the end
+                if !self.sweep {
+                    match &self.proxy {
+                        Some(proxy) if proxied => {
+                            let addr = if target_addr.is_unspecified() {
+                                "resolved by proxy".to_string()
+                            } else {
+                                target_addr.to_string()
+                            };
+                            println!(
+                                "PING {} ({}) via proxy {}:{}",
+                                base, addr, proxy.host, proxy.port
+                            )
+                        }
+                        _ => match crate::pinger::unix::socket_path(&protocol) {
+                            Some(path) => println!("PING {} ({})", base, path.display()),
+                            None => println!(
+                                "PING {} ({}) {}({}) bytes of data.",
+                                base,
+                                target_addr,
+                                cli.size,
+                                cli.size + 28
+                            ),
+                        },
+                    }
+                }
 
-                    let config = crate::pinger::PingerConfig {
-                        ttl: self.cli.ttl,
-                        size: self.cli.size,
-                        timeout: self.cli.timeout,
-                        headers: headers.clone(),
-                    };
+                let config = crate::pinger::PingerConfig {
+                    ttl: cli.ttl,
+                    size: cli.size,
+                    timeout: cli.timeout,
+                    headers: headers.clone(),
+                    http: http_options.clone(),
+                    proxy: self.proxy.clone(),
+                    interface: cli.interface.clone(),
+                };
 
-                    let mut pinger = crate::pinger::create_pinger(
-                        target_string.clone(),
-                        protocol,
-                        target_addr,
-                        config,
-                        client_v4.clone(),
-                        client_v6.clone(),
+                // Host names are followed when their address changes, unless
+                // something else than this host resolves them
+                let re_resolve = cli.re_resolve.filter(|_| {
+                    !proxied
+                        && crate::pinger::unix::socket_path(&protocol).is_none()
+                        && host_to_resolve.parse::<std::net::IpAddr>().is_err()
+                });
+                if re_resolve.is_some() {
+                    self.retargets.insert(
+                        name.clone(),
+                        Retarget {
+                            host: host_to_resolve.clone(),
+                            protocol: protocol.clone(),
+                            config: config.clone(),
+                            addr: target_addr,
+                        },
                     );
+                }
 
-                    if let Err(e) = pinger.start(tx.clone()).await {
-                        eprintln!("Failed to start pinger for {}: {}", target_string, e);
+                let mut pinger = match crate::pinger::create_pinger(
+                    name.clone(),
+                    host_to_resolve.clone(),
+                    protocol.clone(),
+                    target_addr,
+                    config,
+                    self.client_v4.clone(),
+                    self.client_v6.clone(),
+                ) {
+                    Ok(pinger) => pinger,
+                    Err(e) => {
+                        eprintln!("pingx: {}: {}", name, e);
+                        if !multi_target {
+                            return Err(e);
+                        }
                         continue;
                     }
-                    pingers.push(pinger);
+                };
+
+                if let Err(e) = pinger.start(self.tx.clone()).await {
+                    eprintln!("Failed to start pinger for {}: {}", name, e);
+                    continue;
                 }
-                Err(e) => {
-                    eprintln!("pingx: {}: {}", target_string, e);
-                    if !multi_target {
-                        return Err(e);
+                self.schedules.push(Schedule {
+                    name: name.clone(),
+                    pinger,
+                    interval: cli.interval,
+                    count: cli.count.or(self.sweep.then_some(SWEEP_COUNT)),
+                    timeout: cli.timeout,
+                    seq: 1,
+                    next_at: tokio::time::Instant::now(),
+                    finished: false,
+                });
+                if let Some(interval) = re_resolve {
+                    // Targets of the same host name, like the ports of a port
+                    // list, share its watcher
+                    let key = (host_to_resolve.clone(), ip_version);
+                    match self.watchers.get_mut(&key) {
+                        Some(watcher) => watcher.members.push(name.clone()),
+                        None => {
+                            let current = Arc::new(tokio::sync::Mutex::new(target_addr));
+                            let task = crate::resolver::watch(
+                                host_to_resolve.clone(),
+                                protocol.clone(),
+                                ip_version,
+                                current.clone(),
+                                interval,
+                                self.changes_tx.clone(),
+                            );
+                            self.watchers.insert(
+                                key,
+                                Watcher {
+                                    task,
+                                    current,
+                                    members: vec![name.clone()],
+                                },
+                            );
+                        }
                     }
                 }
+                members.push(name);
             }
         }
 
-        drop(tx);
+        if members.len() > 1 {
+            for member in &members {
+                self.target_groups.insert(member.clone(), group.clone());
+            }
+        }
+        if !members.is_empty() {
+            self.groups.push((group, members));
+        }
+        Ok(())
+    }
+}
 
-        if pingers.is_empty() {
-            return Ok(());
+pub struct Session {
+    cli: Cli,
+    targets: Targets,
+}
+
+impl Session {
+    pub fn new(cli: Cli, targets: Targets) -> Self {
+        Self { cli, targets }
+    }
+
+    /// Headers and HTTP options of a target, whose method may differ from
+    /// that of the command line.
+    fn target_http(
+        cli: &Cli,
+        options: &crate::pinger::http::HttpOptions,
+    ) -> Result<(reqwest::header::HeaderMap, crate::pinger::http::HttpOptions)> {
+        let headers = crate::utils::parse_headers(&cli.headers)?;
+        let mut options = options.clone();
+        options.method = crate::utils::parse_http_method(
+            cli.method.as_deref(),
+            !options.body.is_empty(),
+            options.assertions.inspects_body() || options.download,
+        )?;
+        crate::utils::check_http_method(&options.method, options.download, &options.assertions)?;
+        Ok((headers, options))
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let multi_target = self.targets.specs.len() > 1 || !self.targets.lists.is_empty();
+        let quiet = self.cli.quiet;
+
+        let assertions = crate::pinger::http::HttpAssertions::from_cli(&self.cli)?;
+        let body = crate::utils::load_http_body(&self.cli)?;
+        let http_version = crate::pinger::http::HttpVersion::from_cli(&self.cli);
+        // Stands in until a target needs the certificate settings
+        let tls_config = crate::pinger::http::tls::client_config(&Default::default())?;
+        let proxy = match &self.cli.proxy {
+            Some(spec) => Some(Arc::new(crate::pinger::proxy::Proxy::parse(spec)?)),
+            None => None,
+        };
+        let method = crate::utils::parse_http_method(
+            self.cli.method.as_deref(),
+            !body.is_empty(),
+            assertions.inspects_body() || self.cli.download,
+        )?;
+        let http_options = crate::pinger::http::HttpOptions {
+            version: http_version,
+            reuse: !self.cli.no_reuse,
+            max_redirects: self.cli.follow_redirects.then_some(self.cli.max_redirects),
+            method,
+            body,
+            assertions,
+            capture_headers: crate::utils::parse_header_names(&self.cli.capture_headers)?,
+            download: self.cli.download,
+            download_limit: self.cli.download_limit,
+            tls: Arc::new(tls_config),
+            proxy: proxy.clone(),
+        };
+
+        if self
+            .cli
+            .re_resolve
+            .is_some_and(|interval| interval.is_zero())
+        {
+            return Err(anyhow::anyhow!("--re-resolve needs a nonzero interval"));
         }
 
-        let mut interval = tokio::time::interval(self.cli.interval);
-        let mut seq = 1;
-        let count = self.cli.count;
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<models::PingResult>(100);
+        let (changes_tx, mut changes_rx) = tokio::sync::mpsc::channel(16);
+        let start = tokio::time::Instant::now();
+        let mut probes = Probes {
+            tx,
+            http: http_options,
+            proxy,
+            ttl: self.cli.ttl,
+            multi_target,
+            sweep: self.cli.sweep,
+            all_stats: HashMap::new(),
+            target_protocols: HashMap::new(),
+            target_sizes: HashMap::new(),
+            labelled_targets: HashSet::new(),
+            groups: Vec::new(),
+            target_groups: HashMap::new(),
+            schedules: Vec::new(),
+            client_v4: None,
+            client_v6: None,
+            pending_tls: Some(crate::pinger::http::TlsOptions::from_cli(&self.cli)),
+            changes_tx,
+            retargets: HashMap::new(),
+            watchers: HashMap::new(),
+            split_targets: HashSet::new(),
+            address_changes: HashMap::new(),
+            start,
+        };
+
+        for target in &self.targets.specs {
+            probes.add(target).await?;
+        }
+        let mut incoming = (!self.targets.lists.is_empty())
+            .then(|| crate::targets::read_lists(self.targets.lists.clone(), self.cli.clone()));
+
+        if probes.schedules.is_empty() && incoming.is_none() {
+            return Ok(());
+        }
 
         let (stop_tx, mut stop_rx) = tokio::sync::mpsc::channel(1);
         tokio::spawn(async move {
@@ -249,26 +1530,64 @@ impl Session {
 
         let mut inflight_packets = 0;
 
+        // Gap between probes under a rate limit, and when the next may go out
+        let rate_gap = self
+            .cli
+            .rate
+            .or(self.cli.sweep.then_some(SWEEP_RATE))
+            .map(|rate| Duration::from_secs_f64(1.0 / rate as f64));
+        let mut rate_next = start;
+
         loop {
+            let next_tick = probes
+                .schedules
+                .iter()
+                .filter(|s| !s.finished)
+                .map(|s| s.next_at)
+                .min()
+                .map(|at| at.max(rate_next));
+
             tokio::select! {
-                _ = interval.tick(), if !waiting_for_shutdown => {
-                    if let Some(c) = count
-                        && seq > c {
-                            waiting_for_shutdown = true;
-                            if inflight_packets == 0 { break; }
-                            // Reset sleep to wait for stragglers
-                            wait_timeout = Box::pin(tokio::time::sleep(self.cli.timeout + Duration::from_millis(100)));
-                            continue;
+                _ = tokio::time::sleep_until(next_tick.unwrap_or(start)), if next_tick.is_some() => {
+                    let now = tokio::time::Instant::now();
+                    let mut due: Vec<&mut Schedule> = probes
+                        .schedules
+                        .iter_mut()
+                        .filter(|s| !s.finished && s.next_at <= now)
+                        .collect();
+                    // Under a rate limit, the targets waiting the longest go first
+                    if rate_gap.is_some() {
+                        due.sort_by_key(|s| s.next_at);
                     }
-
-                    for pinger in &pingers {
-                        if let Err(e) = pinger.ping(seq).await {
+                    for schedule in due {
+                        let answered = probes.all_stats.get(&schedule.name).is_some_and(|s| s.received > 0);
+                        // A sweep is done with a target once it answers
+                        if schedule.count.is_some_and(|c| schedule.seq > c) || (self.cli.sweep && answered) {
+                            schedule.finished = true;
+                            continue;
+                        }
+                        if let Some(gap) = rate_gap {
+                            let Some(next) = rate_slot(rate_next, now, gap) else {
+                                break;
+                            };
+                            rate_next = next;
+                        }
+                        schedule.next_at += schedule.interval;
+                        if let Err(e) = schedule.pinger.ping(schedule.seq).await {
                             eprintln!("Failed to ping: {}", e);
                         } else {
                             inflight_packets += 1;
                         }
+                        schedule.seq += 1;
+                    }
+                }
+
+                target = async { incoming.as_mut().unwrap().recv().await }, if incoming.is_some() => {
+                    match target {
+                        Some(target) => probes.add(&target).await?,
+                        // Once the lists are read, the session ends with its last target
+                        None => incoming = None,
                     }
-                    seq += 1;
                 }
 
                 _ = &mut deadline_sleep, if has_deadline => {
@@ -279,15 +1598,33 @@ impl Session {
                     break;
                 }
 
+                Some(change) = changes_rx.recv() => {
+                    if probes.follow(&change).await && !quiet {
+                        println!(
+                            "{}",
+                            format!("{}: address changed from {} to {}", change.host, change.from, change.to).yellow()
+                        );
+                    }
+                }
+
                 Some(result) = rx.recv() => {
                     if inflight_packets > 0 { inflight_packets -= 1; }
 
-                    if let Some(stats) = all_stats.get_mut(&result.target) {
+                    // Once a target changed address, its results go to the member of their address
+                    let key = probes.result_key(&result);
+                    if let Some(stats) = probes.all_stats.get_mut(&key) {
+                        let answered = stats.received > 0;
                         stats.update(&result);
+                        if self.cli.sweep && !quiet && !answered && stats.received > 0 {
+                            println!("{} is alive", result.target);
+                        }
                     }
-                    if !quiet {
-                        let protocol = target_protocols.get(&result.target).unwrap_or(&crate::cli::Protocol::Icmp);
-                        Self::print_result(&result, protocol);
+                    if !quiet && !self.cli.sweep {
+                        let protocol = probes.target_protocols.get(&key).unwrap_or(&crate::cli::Protocol::Icmp);
+                        let group = probes.target_groups.get(&key).map(String::as_str);
+                        let labelled = probes.labelled_targets.contains(&result.target);
+                        let result = models::PingResult { target: key, ..result };
+                        Self::print_result(&result, protocol, group, labelled);
                     }
 
                     if waiting_for_shutdown && inflight_packets == 0 {
@@ -299,17 +1636,52 @@ impl Session {
                     break;
                 }
             }
+
+            if !waiting_for_shutdown
+                && incoming.is_none()
+                && probes.schedules.iter().all(|s| s.finished)
+            {
+                waiting_for_shutdown = true;
+                if inflight_packets == 0 {
+                    break;
+                }
+                // Late replies are awaited as long as the slowest target allows
+                let straggler_timeout = probes
+                    .schedules
+                    .iter()
+                    .map(|s| s.timeout)
+                    .max()
+                    .unwrap_or_default()
+                    + Duration::from_millis(100);
+                wait_timeout = Box::pin(tokio::time::sleep(straggler_timeout));
+            }
         }
 
-        for mut p in pingers {
-            p.stop().await.ok();
+        let Probes {
+            all_stats,
+            target_protocols,
+            target_sizes,
+            groups,
+            schedules,
+            watchers,
+            address_changes,
+            ..
+        } = probes;
+        for watcher in watchers.into_values() {
+            watcher.task.abort();
+        }
+        for mut schedule in schedules {
+            schedule.pinger.stop().await.ok();
         }
 
         // JSON Output Logic
         if let Some(json_arg) = &self.cli.json {
             let mut json_results = Vec::new();
 
-            for target_host in targets {
+            for (group, target_host) in groups
+                .iter()
+                .flat_map(|(group, members)| members.iter().map(move |member| (group, member)))
+            {
                 if let Some(stats) = all_stats.get(target_host) {
                     let protocol = target_protocols
                         .get(target_host)
@@ -318,6 +1690,19 @@ impl Session {
                         crate::cli::Protocol::Icmp => "ICMP",
                         crate::cli::Protocol::Tcp(_) => "TCP",
                         crate::cli::Protocol::Http(_) => "HTTP",
+                        crate::cli::Protocol::WebSocket(_) => "WebSocket",
+                        crate::cli::Protocol::Grpc(_) => "gRPC",
+                        crate::cli::Protocol::Dns(_) => "DNS",
+                        crate::cli::Protocol::Udp(_) => "UDP",
+                        crate::cli::Protocol::Ntp(_) => "NTP",
+                        crate::cli::Protocol::Stun(_) => "STUN",
+                        crate::cli::Protocol::Reflector(_) => "Reflector",
+                        crate::cli::Protocol::Arp => "ARP",
+                        crate::cli::Protocol::Ndp => "NDP",
+                        crate::cli::Protocol::Redis(_) => "Redis",
+                        crate::cli::Protocol::Memcached(_) => "Memcached",
+                        crate::cli::Protocol::Postgres(_) => "PostgreSQL",
+                        crate::cli::Protocol::Unix(_) => "Unix",
                     }
                     .to_string();
 
@@ -364,11 +1749,20 @@ impl Session {
                         (0.0, 0.0, 0.0, 0.0, 0.0)
                     };
 
+                    // Addresses from --all-addrs or --re-resolve share the name of their target
+                    let target = Self::split_member_addr(target_host, group).0;
                     json_results.push(JsonResult {
-                        target: target_host.clone(),
+                        target: target.to_string(),
                         protocol: protocol_str,
                         ip: stats._address.to_string(),
-                        packet_size: self.cli.size,
+                        port: match protocol {
+                            crate::cli::Protocol::Tcp(port) => Some(*port),
+                            _ => None,
+                        },
+                        packet_size: target_sizes
+                            .get(target_host)
+                            .copied()
+                            .unwrap_or(self.cli.size),
                         ttl: self.cli.ttl,
                         sent: stats.transmitted,
                         received: stats.received,
@@ -379,6 +1773,56 @@ impl Session {
                         max,
                         mdev,
                         jitter,
+                        assertion_failures: (stats.assertion_failures > 0)
+                            .then_some(stats.assertion_failures),
+                        tls_errors: (stats.tls_errors > 0).then_some(stats.tls_errors),
+                        unhealthy: (stats.unhealthy > 0).then_some(stats.unhealthy),
+                        protocol_errors: (stats.protocol_errors > 0)
+                            .then_some(stats.protocol_errors),
+                        http_versions: (!stats.http_versions.is_empty()).then(|| {
+                            stats
+                                .http_versions
+                                .iter()
+                                .map(|v| Self::http_version_label(*v).to_string())
+                                .collect()
+                        }),
+                        http_connections: (!stats.http_timings.is_empty()).then_some(
+                            JsonConnections {
+                                new: stats.new_connections,
+                                reused: stats.reused_connections,
+                            },
+                        ),
+                        http_timing: JsonHttpTiming::from_timings(&stats.http_timings),
+                        download: JsonDownload::from_downloads(&stats.downloads),
+                        tcp_timing: JsonTcpTiming::from_details(&stats.tcp_timings),
+                        ws_handshake: JsonWsHandshake::from_handshakes(&stats.ws_handshakes),
+                        redirect_downgrades: (stats.redirect_downgrades > 0)
+                            .then_some(stats.redirect_downgrades),
+                        redirect_loops: (stats.redirect_loops > 0).then_some(stats.redirect_loops),
+                        headers: JsonValueCounts::from_stats(&stats.captured_headers),
+                        dns: JsonDns::from_stats(stats),
+                        ntp: JsonNtp::from_samples(&stats.ntp_samples),
+                        reflector: JsonReflector::from_stats(stats),
+                        neighbor: JsonNeighbor::from_stats(stats),
+                        stun: JsonStun::from_stats(stats),
+                        // Listed once, on the entry of the address probed last
+                        address_changes: address_changes
+                            .get(target)
+                            .filter(|changes| {
+                                changes
+                                    .last()
+                                    .is_some_and(|(_, to, _)| *to == stats._address)
+                            })
+                            .map(|changes| {
+                                changes
+                                    .iter()
+                                    .map(|(from, to, time)| JsonAddressChange {
+                                        from: from.to_string(),
+                                        to: to.to_string(),
+                                        time: (time.as_secs_f64() * 1_000_000.0).round() / 1000.0,
+                                    })
+                                    .collect()
+                            }),
                     });
                 }
             }
@@ -403,14 +1847,35 @@ impl Session {
             return Ok(());
         }
 
+        if self.cli.sweep {
+            Self::render_sweep(&groups, &all_stats);
+            return Ok(());
+        }
+
         // Collect table data and calculate global column widths
         let mut tables = Vec::new();
         let mut global_key_widths = [0usize; 3];
         let mut global_val_widths = [0usize; 3];
 
-        for target_host in targets {
-            if let Some(stats) = all_stats.get(target_host) {
-                let table = Self::prepare_table_data(stats);
+        for (group, members) in &groups {
+            let mut group_tables = Vec::new();
+            for target_host in members {
+                if let Some(stats) = all_stats.get(target_host) {
+                    let table = Self::prepare_table_data(stats);
+                    for r in 0..3 {
+                        for c in 0..3 {
+                            global_key_widths[c] =
+                                std::cmp::max(global_key_widths[c], table.rows[r][c].key.len());
+                            global_val_widths[c] =
+                                std::cmp::max(global_val_widths[c], table.rows[r][c].val.len());
+                        }
+                    }
+                    group_tables.push((target_host.clone(), table));
+                }
+            }
+            let mut rollups = Vec::new();
+            for (label, stats) in Self::address_rollups(group, members, &all_stats) {
+                let table = Self::prepare_table_data(&stats);
                 for r in 0..3 {
                     for c in 0..3 {
                         global_key_widths[c] =
@@ -419,23 +1884,149 @@ impl Session {
                             std::cmp::max(global_val_widths[c], table.rows[r][c].val.len());
                     }
                 }
-                tables.push((target_host.clone(), table));
+                rollups.push((label, table));
             }
+            tables.push((group.clone(), group_tables, rollups));
         }
 
-        // Print all tables with global widths
-        for (target, table) in tables {
-            Self::render_table(&target, &table, &global_key_widths, &global_val_widths);
+        // Print all tables with global widths. Targets expanded into several
+        // pingers get one heading with a sub-table per member.
+        for (group, group_tables, rollups) in tables {
+            if group_tables.len() > 1 {
+                Self::render_title(
+                    &format!("=== {} ping statistics ===", group),
+                    &global_key_widths,
+                    &global_val_widths,
+                );
+                for (target, table) in group_tables {
+                    let label = Self::member_label(&target, &group);
+                    Self::render_table(
+                        &format!("--- {} ---", label),
+                        &table,
+                        &global_key_widths,
+                        &global_val_widths,
+                    );
+                    Self::render_detail_summary(all_stats.get(&target));
+                }
+                for (label, table) in rollups {
+                    Self::render_table(
+                        &format!("--- {} ---", label),
+                        &table,
+                        &global_key_widths,
+                        &global_val_widths,
+                    );
+                }
+            } else {
+                for (target, table) in group_tables {
+                    Self::render_table(
+                        &format!("=== {} ping statistics ===", target),
+                        &table,
+                        &global_key_widths,
+                        &global_val_widths,
+                    );
+                    Self::render_detail_summary(all_stats.get(&target));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Picks the addresses to probe: every resolved address with
+    /// `--all-addrs`, otherwise the one chosen by `select_target_addr`.
+    async fn select_target_addrs(
+        host: &str,
+        protocol: &crate::cli::Protocol,
+        ip_version: IpVersion,
+        proxied: bool,
+        all_addrs: bool,
+    ) -> Result<Vec<std::net::IpAddr>> {
+        if !all_addrs || proxied || crate::pinger::unix::socket_path(protocol).is_some() {
+            return Ok(vec![
+                Self::select_target_addr(host, protocol, ip_version, proxied).await?,
+            ]);
         }
+        let mut addrs = resolve_host(host, ip_version).await?;
+        let mut seen = HashSet::new();
+        addrs.retain(|addr| seen.insert(*addr));
+        Ok(addrs)
+    }
 
-        Ok(())
+    /// Picks the address to probe. Through a proxy the target is resolved
+    /// and reached by the proxy, so a local lookup is only informative and
+    /// an unresolvable target is shown as the unspecified address, like
+    /// targets on a Unix domain socket.
+    async fn select_target_addr(
+        host: &str,
+        protocol: &crate::cli::Protocol,
+        ip_version: IpVersion,
+        proxied: bool,
+    ) -> Result<std::net::IpAddr> {
+        if crate::pinger::unix::socket_path(protocol).is_some() {
+            return Ok(std::net::Ipv4Addr::UNSPECIFIED.into());
+        }
+        if proxied {
+            let addrs = resolve_host(host, ip_version).await.unwrap_or_default();
+            return Ok(addrs
+                .first()
+                .copied()
+                .unwrap_or(std::net::Ipv4Addr::UNSPECIFIED.into()));
+        }
+        let addrs = resolve_host(host, ip_version).await?;
+        crate::happy_eyeballs::select_best_addr(addrs, protocol).await
     }
 
-    fn print_result(result: &models::PingResult, protocol: &crate::cli::Protocol) {
+    fn print_result(
+        result: &models::PingResult,
+        protocol: &crate::cli::Protocol,
+        group: Option<&str>,
+        labelled: bool,
+    ) {
         let seq_prefix = match protocol {
             crate::cli::Protocol::Icmp => "icmp_seq",
             crate::cli::Protocol::Tcp(_) => "tcp_seq",
             crate::cli::Protocol::Http(_) => "http_seq",
+            crate::cli::Protocol::WebSocket(_) => "ws_seq",
+            crate::cli::Protocol::Grpc(_) => "grpc_seq",
+            crate::cli::Protocol::Dns(_) => "dns_seq",
+            crate::cli::Protocol::Udp(_) => "udp_seq",
+            crate::cli::Protocol::Ntp(_) => "ntp_seq",
+            crate::cli::Protocol::Stun(_) => "stun_seq",
+            crate::cli::Protocol::Reflector(_) => "refl_seq",
+            crate::cli::Protocol::Arp => "arp_seq",
+            crate::cli::Protocol::Ndp => "ndp_seq",
+            crate::cli::Protocol::Redis(_) => "redis_seq",
+            crate::cli::Protocol::Memcached(_) => "mc_seq",
+            crate::cli::Protocol::Postgres(_) => "pg_seq",
+            crate::cli::Protocol::Unix(_) => "unix_seq",
+        };
+
+        // Members of an expanded target are labelled with the owning host, and
+        // targets with a label in the targets file with it. Targets only a
+        // proxy could resolve have no address to show.
+        let unresolved = result.target_addr.is_unspecified();
+        let (from, suffix) = match group {
+            Some(group) if unresolved => (
+                format!("{} {}", group, Self::member_label(&result.target, group)),
+                format!(" ({} {})", group, Self::member_label(&result.target, group)),
+            ),
+            Some(group) => {
+                // The address of a member from `--all-addrs` is already shown
+                let label = match Self::split_member_addr(&result.target, group) {
+                    (base, Some(_)) if base == group => String::new(),
+                    (base, _) => format!(" {}", Self::member_label(base, group)),
+                };
+                (
+                    format!("{} ({}){}", group, result.target_addr, label),
+                    format!(" ({} {})", group, Self::member_label(&result.target, group)),
+                )
+            }
+            None if unresolved => (result.target.clone(), String::new()),
+            None if labelled => (
+                format!("{} ({})", result.target, result.target_addr),
+                format!(" ({})", result.target),
+            ),
+            None => (result.target_addr.to_string(), String::new()),
         };
 
         match &result.status {
@@ -451,7 +2042,7 @@ impl Session {
                         println!(
                             "{} bytes from {}: {}={}{} time={:.3} ms",
                             result.bytes,
-                            result.target_addr,
+                            from,
                             seq_prefix,
                             result.seq,
                             ttl_str,
@@ -459,22 +2050,454 @@ impl Session {
                         );
                     }
                     _ => {
+                        let phases = match &result.detail {
+                            Some(models::ProbeDetail::Http(detail)) => {
+                                let download = detail.download.as_ref().map(Self::format_download);
+                                Self::format_http_detail(detail)
+                                    + &download.unwrap_or_default()
+                                    + &Self::format_captured_headers(&detail.headers)
+                            }
+                            Some(models::ProbeDetail::Tcp(detail)) => {
+                                Self::format_tcp_detail(detail)
+                            }
+                            Some(models::ProbeDetail::WebSocket(detail)) => {
+                                Self::format_ws_detail(detail)
+                            }
+                            Some(models::ProbeDetail::Grpc(detail)) => {
+                                format!(" {}", detail.serving.unwrap_or_default())
+                                    + &Self::format_http_detail(&detail.http)
+                            }
+                            Some(models::ProbeDetail::Dns(detail)) => {
+                                Self::format_dns_detail(detail)
+                            }
+                            Some(models::ProbeDetail::Ntp(detail)) => {
+                                Self::format_ntp_detail(detail)
+                            }
+                            Some(models::ProbeDetail::Reflector(detail)) => {
+                                Self::format_reflector_detail(detail)
+                            }
+                            Some(models::ProbeDetail::Neighbor(detail)) => {
+                                Self::format_neighbor_detail(detail)
+                            }
+                            Some(models::ProbeDetail::Service(detail)) => {
+                                Self::format_service_detail(detail)
+                            }
+                            Some(models::ProbeDetail::Stun(detail)) => {
+                                Self::format_stun_detail(detail)
+                            }
+                            None => String::new(),
+                        };
                         println!(
-                            "from {}: {}={} time={:.3} ms",
-                            result.target_addr,
+                            "from {}: {}={} time={:.3} ms{}",
+                            from,
                             seq_prefix,
                             result.seq,
-                            result.rtt.as_secs_f64() * 1000.0
+                            result.rtt.as_secs_f64() * 1000.0,
+                            phases
                         );
                     }
                 }
             }
             models::ProbeStatus::Timeout => {
-                println!("Request timeout for {}={}", seq_prefix, result.seq);
+                println!(
+                    "Request timeout for {}={}{}",
+                    seq_prefix, result.seq, suffix
+                );
             }
             models::ProbeStatus::Error(e) => {
-                eprintln!("Error for {}={}: {}", seq_prefix, result.seq, e);
+                eprintln!("Error for {}={}{}: {}", seq_prefix, result.seq, suffix, e);
+            }
+            models::ProbeStatus::TlsError(e) => {
+                eprintln!(
+                    "TLS error for {}={}{}: {}",
+                    seq_prefix, result.seq, suffix, e
+                );
+            }
+            models::ProbeStatus::AssertionFailed(e) => {
+                eprintln!(
+                    "Assertion failed for {}={}{}: {}",
+                    seq_prefix, result.seq, suffix, e
+                );
+            }
+            models::ProbeStatus::Unhealthy(status) => {
+                eprintln!(
+                    "Unhealthy for {}={}{}: {} time={:.3} ms",
+                    seq_prefix,
+                    result.seq,
+                    suffix,
+                    status,
+                    result.rtt.as_secs_f64() * 1000.0
+                );
+            }
+            models::ProbeStatus::ProtocolError(e) => {
+                eprintln!(
+                    "Protocol error for {}={}{}: {} time={:.3} ms",
+                    seq_prefix,
+                    result.seq,
+                    suffix,
+                    e,
+                    result.rtt.as_secs_f64() * 1000.0
+                );
+            }
+        }
+
+        if let Some(models::ProbeDetail::Http(detail)) = &result.detail
+            && detail
+                .redirects
+                .iter()
+                .any(|hop| (300..400).contains(&hop.status))
+        {
+            for (i, hop) in detail.redirects.iter().enumerate() {
+                println!("  {}", Self::format_redirect_hop(i + 1, hop));
+            }
+        }
+    }
+
+    fn format_redirect_hop(index: usize, hop: &models::RedirectHop) -> String {
+        let mut line = format!(
+            "hop {}: {} {} time={:.3} ms",
+            index,
+            hop.status,
+            hop.url,
+            hop.latency.as_secs_f64() * 1000.0
+        );
+        if hop.downgrade {
+            line.push_str(" [https->http downgrade]");
+        }
+        if hop.looped {
+            line.push_str(" [loop]");
+        }
+        line
+    }
+
+    fn format_http_detail(detail: &models::HttpDetail) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let timing = &detail.timing;
+        let handshake = if detail.version == http::Version::HTTP_3 {
+            format!("quic={:.3}", ms(timing.quic))
+        } else {
+            format!(
+                "connect={:.3} tls={:.3}",
+                ms(timing.connect),
+                ms(timing.tls)
+            )
+        };
+        // The proxy phase only appears on connections opened through a proxy
+        let handshake = if timing.proxy.is_zero() {
+            handshake
+        } else {
+            format!("proxy={:.3} {}", ms(timing.proxy), handshake)
+        };
+        format!(
+            " {} conn={} (dns={:.3} {} ttfb={:.3} transfer={:.3} ms)",
+            Self::http_version_label(detail.version),
+            if detail.reused { "reused" } else { "new" },
+            ms(timing.dns),
+            handshake,
+            ms(timing.ttfb),
+            ms(timing.transfer)
+        )
+    }
+
+    fn format_download(download: &models::Download) -> String {
+        match download.mbps() {
+            Some(mbps) => format!(" download={} B {:.3} Mbit/s", download.bytes, mbps),
+            None => format!(" download={} B", download.bytes),
+        }
+    }
+
+    /// Summary line of downloads, e.g.
+    /// `download: avg 1048576 B at 85.213 Mbit/s (min 80.112, max 90.001), ttfb avg 12.345 ms`.
+    fn format_download_summary(stats: &models::PingStats) -> Option<String> {
+        let download = JsonDownload::from_downloads(&stats.downloads)?;
+        let ttfb = JsonPhase::from_durations(stats.http_timings.iter().map(|t| t.ttfb));
+        let rate = match download.mbps {
+            Some(mbps) => format!(
+                " at {:.3} Mbit/s (min {:.3}, max {:.3})",
+                mbps.avg, mbps.min, mbps.max
+            ),
+            None => String::new(),
+        };
+        Some(format!(
+            "download: avg {:.0} B{}, ttfb avg {:.3} ms",
+            download.bytes.avg, rate, ttfb.avg
+        ))
+    }
+
+    /// Captured headers as ` [name=value ...]`, with `-` for missing ones
+    /// and values containing spaces quoted.
+    fn format_captured_headers(headers: &[(String, Option<String>)]) -> String {
+        if headers.is_empty() {
+            return String::new();
+        }
+        let fields: Vec<String> = headers
+            .iter()
+            .map(|(name, value)| match value.as_deref() {
+                None => format!("{}=-", name),
+                Some(value) if value.contains(' ') => format!("{}=\"{}\"", name, value),
+                Some(value) => format!("{}={}", name, value),
+            })
+            .collect();
+        format!(" [{}]", fields.join(" "))
+    }
+
+    /// Values of a response field with their count and percentage of
+    /// responses, most frequent first.
+    fn value_distribution(field: &models::ValueCounts) -> Vec<(&Option<String>, u64, f64)> {
+        let total = field.total().max(1) as f64;
+        let mut values: Vec<_> = field
+            .counts
+            .iter()
+            .map(|(value, count)| (value, *count, 100.0 * *count as f64 / total))
+            .collect();
+        values.sort_by_key(|&(_, count, _)| std::cmp::Reverse(count));
+        values
+    }
+
+    /// Summary line of a response field, e.g. `x-cache: HIT 75% (3), MISS 25% (1)`.
+    /// Only the most frequent values are listed.
+    fn format_value_summary(field: &models::ValueCounts) -> String {
+        const MAX_VALUES: usize = 5;
+        let values = Self::value_distribution(field);
+        let mut parts: Vec<String> = values
+            .iter()
+            .take(MAX_VALUES)
+            .map(|(value, count, percent)| {
+                format!(
+                    "{} {:.0}% ({})",
+                    value.as_deref().unwrap_or("-"),
+                    percent,
+                    count
+                )
+            })
+            .collect();
+        if values.len() > MAX_VALUES {
+            parts.push(format!("{} more", values.len() - MAX_VALUES));
+        }
+        format!("{}: {}", field.name, parts.join(", "))
+    }
+
+    pub fn format_mac(mac: &[u8; 6]) -> String {
+        mac.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// e.g. ` mac=02:00:00:00:00:01 [DUP! also 02:00:00:00:00:02]`
+    fn format_neighbor_detail(detail: &models::NeighborDetail) -> String {
+        let mut s = format!(" mac={}", Self::format_mac(&detail.mac));
+        if !detail.duplicates.is_empty() {
+            let macs: Vec<String> = detail.duplicates.iter().map(Self::format_mac).collect();
+            s.push_str(&format!(" [DUP! also {}]", macs.join(", ")));
+        }
+        if !detail.shared_with.is_empty() {
+            let addrs: Vec<String> = detail.shared_with.iter().map(|a| a.to_string()).collect();
+            s.push_str(&format!(
+                " [duplicate MAC, also answers for {}]",
+                addrs.join(", ")
+            ));
+        }
+        s
+    }
+
+    /// e.g. ` mapped=203.0.113.5:40001 [mapping changed from 203.0.113.5:40000]`
+    fn format_stun_detail(detail: &models::StunDetail) -> String {
+        let mut s = format!(" mapped={}", detail.mapped);
+        if let Some(previous) = detail.previous {
+            s.push_str(&format!(" [mapping changed from {}]", previous));
+        }
+        s
+    }
+
+    /// e.g. ` auth=sasl connect=0.210 ms tls=1.830 ms`
+    fn format_service_detail(detail: &models::ServiceDetail) -> String {
+        let mut s = format!(
+            " {} connect={:.3} ms",
+            detail.reply,
+            detail.connect.as_secs_f64() * 1000.0
+        );
+        if let Some(tls) = detail.tls {
+            s.push_str(&format!(" tls={:.3} ms", tls.as_secs_f64() * 1000.0));
+        }
+        s
+    }
+
+    /// e.g. ` NOERROR answers=2 [truncated, retried over TCP]`
+    fn format_dns_detail(detail: &models::DnsDetail) -> String {
+        let mut s = format!(" {} answers={}", detail.rcode, detail.answers);
+        if detail.truncated {
+            s.push_str(" [truncated, retried over TCP]");
+        }
+        s
+    }
+
+    /// e.g. ` offset=+0.512 ms delay=0.430 ms stratum=2 refid=192.0.2.1 leap=none`
+    fn format_ntp_detail(detail: &models::NtpDetail) -> String {
+        format!(
+            " offset={:+.3} ms delay={:.3} ms stratum={} refid={} leap={}",
+            detail.offset * 1000.0,
+            detail.delay.as_secs_f64() * 1000.0,
+            detail.stratum,
+            detail.refid,
+            detail.leap
+        )
+    }
+
+    /// e.g. `offset: min -0.512, avg +0.103, max +0.920, stdev 0.412 ms`
+    fn format_ntp_summary(samples: &[models::NtpDetail]) -> Option<String> {
+        if samples.is_empty() {
+            return None;
+        }
+        let offsets: Vec<f64> = samples.iter().map(|s| s.offset * 1000.0).collect();
+        let min = offsets.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = offsets.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let avg = offsets.iter().sum::<f64>() / offsets.len() as f64;
+        let stdev =
+            (offsets.iter().map(|o| (o - avg).powi(2)).sum::<f64>() / offsets.len() as f64).sqrt();
+        Some(format!(
+            "offset: min {:+.3}, avg {:+.3}, max {:+.3}, stdev {:.3} ms",
+            min, avg, max, stdev
+        ))
+    }
+
+    /// e.g. ` fwd=+0.120 ms rev=+0.110 ms proc=0.015 ms`
+    fn format_reflector_detail(detail: &models::ReflectorDetail) -> String {
+        format!(
+            " fwd={:+.3} ms rev={:+.3} ms proc={:.3} ms",
+            detail.forward * 1000.0,
+            detail.reverse * 1000.0,
+            detail.processing.as_secs_f64() * 1000.0
+        )
+    }
+
+    fn format_reflector_summary(stats: &models::PingStats) -> Vec<String> {
+        let Some(loss) = stats.directional_loss() else {
+            return Vec::new();
+        };
+        let samples = &stats.reflector_samples;
+        let avg = |f: fn(&models::ReflectorDetail) -> f64| {
+            samples.iter().map(f).sum::<f64>() / samples.len() as f64 * 1000.0
+        };
+        let percent = |lost: u64, sent: u64| 100.0 * lost as f64 / sent.max(1) as f64;
+        vec![
+            format!(
+                "one-way: forward avg {:+.3} ms, reverse avg {:+.3} ms, processing avg {:.3} ms",
+                avg(|s| s.forward),
+                avg(|s| s.reverse),
+                avg(|s| s.processing.as_secs_f64())
+            ),
+            format!(
+                "loss: forward {}/{} ({:.1}%), reverse {}/{} ({:.1}%)",
+                loss.forward_lost,
+                loss.forward_sent,
+                percent(loss.forward_lost, loss.forward_sent),
+                loss.reverse_lost,
+                loss.reverse_sent,
+                percent(loss.reverse_lost, loss.reverse_sent)
+            ),
+        ]
+    }
+
+    fn format_tcp_detail(detail: &models::TcpDetail) -> String {
+        format!(
+            " (proxy={:.3} connect={:.3} ms)",
+            detail.proxy.as_secs_f64() * 1000.0,
+            detail.connect.as_secs_f64() * 1000.0
+        )
+    }
+
+    /// Shows the handshake on the probe that opened the connection, which
+    /// is not part of the ping/pong time.
+    fn format_ws_detail(detail: &models::WsDetail) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let Some(handshake) = &detail.handshake else {
+            return " conn=reused".to_string();
+        };
+        let proxy = if handshake.proxy.is_zero() {
+            String::new()
+        } else {
+            format!("proxy={:.3} ", ms(handshake.proxy))
+        };
+        format!(
+            " conn=new (dns={:.3} {}connect={:.3} tls={:.3} upgrade={:.3} ms)",
+            ms(handshake.dns),
+            proxy,
+            ms(handshake.connect),
+            ms(handshake.tls),
+            ms(handshake.upgrade)
+        )
+    }
+
+    fn http_version_label(version: http::Version) -> &'static str {
+        match version {
+            http::Version::HTTP_09 => "HTTP/0.9",
+            http::Version::HTTP_10 => "HTTP/1.0",
+            http::Version::HTTP_2 => "HTTP/2",
+            http::Version::HTTP_3 => "HTTP/3",
+            _ => "HTTP/1.1",
+        }
+    }
+
+    /// Short label of an expanded pinger within its group, e.g. `port 22` for
+    /// `host:22`, or the address for `host@addr` from `--all-addrs`.
+    fn member_label(name: &str, group: &str) -> String {
+        let (base, addr) = Self::split_member_addr(name, group);
+        let port = match base.rsplit_once(':') {
+            Some((host, port)) if host == group => Some(format!("port {}", port)),
+            _ => None,
+        };
+        match (addr, port) {
+            (Some(addr), Some(port)) => format!("{} {}", addr, port),
+            (Some(addr), None) => addr.to_string(),
+            (None, Some(port)) => port,
+            (None, None) => name.to_string(),
+        }
+    }
+
+    /// Stats of the addresses of each target of a group probed with
+    /// `--all-addrs` taken together, with the label of the target.
+    fn address_rollups(
+        group: &str,
+        members: &[String],
+        all_stats: &HashMap<String, models::PingStats>,
+    ) -> Vec<(String, models::PingStats)> {
+        let mut targets: Vec<(&str, Vec<&models::PingStats>)> = Vec::new();
+        for member in members {
+            let (base, Some(_)) = Self::split_member_addr(member, group) else {
+                continue;
+            };
+            let Some(stats) = all_stats.get(member) else {
+                continue;
+            };
+            match targets.iter_mut().find(|(target, _)| *target == base) {
+                Some((_, all)) => all.push(stats),
+                None => targets.push((base, vec![stats])),
+            }
+        }
+        targets
+            .into_iter()
+            .map(|(base, all)| {
+                let label = if base == group {
+                    "all addresses".to_string()
+                } else {
+                    format!("{}, all addresses", Self::member_label(base, group))
+                };
+                (label, models::PingStats::combined(base.to_string(), &all))
+            })
+            .collect()
+    }
+
+    /// Splits the pinger of one address of a target, named `name@addr`, into
+    /// the target name and the address.
+    fn split_member_addr<'a>(name: &'a str, group: &str) -> (&'a str, Option<&'a str>) {
+        match name.rsplit_once('@') {
+            Some((base, addr))
+                if base.starts_with(group) && addr.parse::<std::net::IpAddr>().is_ok() =>
+            {
+                (base, Some(addr))
             }
+            _ => (name, None),
         }
     }
 
@@ -577,7 +2600,109 @@ impl Session {
         }
     }
 
-    fn render_table(target: &str, table: &TableData, k_widths: &[usize; 3], v_widths: &[usize; 3]) {
+    /// Prints download throughput and the value distribution of each
+    /// captured header below a table.
+    fn render_detail_summary(stats: Option<&models::PingStats>) {
+        let Some(stats) = stats else {
+            return;
+        };
+        if let Some(line) = Self::format_download_summary(stats) {
+            println!("{}", line);
+        }
+        for header in &stats.captured_headers {
+            println!("{}", Self::format_value_summary(header));
+        }
+        if let Some(line) = Self::format_ntp_summary(&stats.ntp_samples) {
+            println!("{}", line);
+        }
+        for line in Self::format_reflector_summary(stats) {
+            println!("{}", line);
+        }
+        if !stats.neighbor_macs.counts.is_empty() {
+            println!("{}", Self::format_value_summary(&stats.neighbor_macs));
+            if stats.duplicate_addresses > 0 || stats.duplicate_macs > 0 {
+                println!(
+                    "conflicts: {} duplicate address, {} duplicate MAC",
+                    stats.duplicate_addresses, stats.duplicate_macs
+                );
+            }
+        }
+        if !stats.stun_mappings.counts.is_empty() {
+            println!("{}", Self::format_value_summary(&stats.stun_mappings));
+            if stats.mapping_changes > 0 {
+                println!("mapping changes: {}", stats.mapping_changes);
+            }
+        }
+        if !stats.dns_rcodes.counts.is_empty() {
+            println!("{}", Self::format_value_summary(&stats.dns_rcodes));
+            if stats.dns_truncated > 0 {
+                println!(
+                    "truncated: {} of {} answers, retried over TCP",
+                    stats.dns_truncated,
+                    stats.dns_rcodes.total()
+                );
+            }
+        }
+    }
+
+    /// Prints the targets of a sweep that answered, with their average
+    /// round trip, then those that did not.
+    fn render_sweep(
+        groups: &[(String, Vec<String>)],
+        all_stats: &HashMap<String, models::PingStats>,
+    ) {
+        let (alive, unreachable) = Self::sweep_split(groups, all_stats);
+        let width = alive
+            .iter()
+            .chain(&unreachable)
+            .map(|(target, _)| target.len())
+            .max()
+            .unwrap_or(0);
+
+        println!(
+            "\n{}",
+            format!(
+                "=== sweep statistics: {} alive, {} unreachable ===",
+                alive.len(),
+                unreachable.len()
+            )
+            .bold()
+            .blue()
+        );
+        if !alive.is_empty() {
+            println!("{}", "alive:".bold());
+            for (target, stats) in &alive {
+                let avg = stats.rtts.iter().sum::<Duration>() / stats.rtts.len() as u32;
+                println!(
+                    "  {:<width$}  {:.3} ms",
+                    target,
+                    avg.as_secs_f64() * 1000.0,
+                    width = width
+                );
+            }
+        }
+        if !unreachable.is_empty() {
+            println!("{}", "unreachable:".bold());
+            for (target, _) in &unreachable {
+                println!("  {}", target);
+            }
+        }
+    }
+
+    /// The targets of a sweep that answered at least once, and the others,
+    /// in the order they were given.
+    fn sweep_split<'a>(
+        groups: &'a [(String, Vec<String>)],
+        all_stats: &'a HashMap<String, models::PingStats>,
+    ) -> (SweepTargets<'a>, SweepTargets<'a>) {
+        groups
+            .iter()
+            .flat_map(|(_, members)| members)
+            .filter_map(|target| all_stats.get(target).map(|stats| (target, stats)))
+            .partition(|(_, stats)| stats.received > 0)
+    }
+
+    fn render_title(title: &str, k_widths: &[usize; 3], v_widths: &[usize; 3]) {
         let sep = " | ";
 
         // Calculate total width for centering title
@@ -585,9 +2710,8 @@ impl Session {
         let col_widths: Vec<usize> = (0..3).map(|i| k_widths[i] + 1 + v_widths[i]).collect();
         let total_width = col_widths.iter().sum::<usize>() + (sep.len() * 2);
 
-        let title_clean = format!("=== {} ping statistics ===", target);
-        let padding = if total_width > title_clean.len() {
-            (total_width - title_clean.len()) / 2
+        let padding = if total_width > title.len() {
+            (total_width - title.len()) / 2
         } else {
             0
         };
@@ -595,9 +2719,15 @@ impl Session {
         println!(
             "\n{:padding$}{}",
             "",
-            format!("=== {} ping statistics ===", target.bold()).blue(),
+            title.bold().blue(),
             padding = padding
         );
+    }
+
+    fn render_table(title: &str, table: &TableData, k_widths: &[usize; 3], v_widths: &[usize; 3]) {
+        let sep = " | ";
+
+        Self::render_title(title, k_widths, v_widths);
 
         for (r_idx, row) in table.rows.iter().enumerate() {
             let is_last_row = r_idx == 2;
@@ -639,9 +2769,27 @@ struct TableData {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::time::Duration;
 
+    /// A probe result of the `test` target, with what the protocol reported.
+    fn result(
+        seq: u64,
+        status: models::ProbeStatus,
+        detail: models::ProbeDetail,
+    ) -> models::PingResult {
+        models::PingResult {
+            target: "test".to_string(),
+            target_addr: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            seq,
+            bytes: 0,
+            ttl: None,
+            rtt: Duration::from_millis(10),
+            status,
+            detail: Some(detail),
+        }
+    }
+
     #[test]
     fn test_stats_calculation() {
         let mut stats =
@@ -656,6 +2804,7 @@ mod tests {
             ttl: Some(64),
             rtt: Duration::from_millis(10),
             status: models::ProbeStatus::Success,
+            detail: None,
         });
         stats.update(&models::PingResult {
             target: "test".to_string(),
@@ -665,6 +2814,7 @@ mod tests {
             ttl: Some(64),
             rtt: Duration::from_millis(20),
             status: models::ProbeStatus::Success,
+            detail: None,
         });
         stats.update(&models::PingResult {
             target: "test".to_string(),
@@ -674,6 +2824,7 @@ mod tests {
             ttl: Some(64),
             rtt: Duration::from_millis(30),
             status: models::ProbeStatus::Success,
+            detail: None,
         });
 
         let table = Session::prepare_table_data(&stats);
@@ -727,4 +2878,421 @@ mod tests {
         assert_eq!(get_val("mdev:"), "-");
         assert_eq!(get_val("jitter:"), "-");
     }
+
+    #[test]
+    fn test_http_timing_summary() {
+        assert!(JsonHttpTiming::from_timings(&[]).is_none());
+
+        let timing = |connect: u64, ttfb: u64| models::HttpTiming {
+            connect: Duration::from_millis(connect),
+            ttfb: Duration::from_millis(ttfb),
+            total: Duration::from_millis(connect + ttfb),
+            ..Default::default()
+        };
+        let summary = JsonHttpTiming::from_timings(&[timing(10, 20), timing(0, 40)]).unwrap();
+
+        assert_eq!(summary.connect.min, 0.0);
+        assert_eq!(summary.connect.avg, 5.0);
+        assert_eq!(summary.connect.max, 10.0);
+        assert_eq!(summary.ttfb.avg, 30.0);
+        assert_eq!(summary.total.min, 30.0);
+        assert_eq!(summary.total.max, 40.0);
+        assert_eq!(summary.dns.max, 0.0);
+        assert!(summary.quic.is_none());
+
+        let quic = models::HttpTiming {
+            quic: Duration::from_millis(15),
+            ..Default::default()
+        };
+        let summary = JsonHttpTiming::from_timings(&[quic, Default::default()]).unwrap();
+        assert_eq!(summary.quic.unwrap().avg, 7.5);
+    }
+
+    #[test]
+    fn test_format_http_detail() {
+        let detail = models::HttpDetail {
+            version: http::Version::HTTP_3,
+            reused: false,
+            timing: models::HttpTiming {
+                quic: Duration::from_millis(12),
+                ..Default::default()
+            },
+            redirects: Vec::new(),
+            headers: Vec::new(),
+            download: None,
+        };
+        let line = Session::format_http_detail(&detail);
+        assert!(line.starts_with(" HTTP/3 conn=new "));
+        assert!(line.contains("quic=12.000"));
+        assert!(!line.contains("connect="));
+
+        let detail = models::HttpDetail {
+            version: http::Version::HTTP_2,
+            reused: true,
+            ..Default::default()
+        };
+        assert!(
+            Session::format_http_detail(&detail).contains("HTTP/2 conn=reused (dns=0.000 connect=")
+        );
+    }
+
+    #[test]
+    fn test_format_redirect_hop() {
+        let hop = models::RedirectHop {
+            url: "https://example.com/".to_string(),
+            status: 301,
+            latency: Duration::from_millis(5),
+            downgrade: true,
+            looped: false,
+        };
+        assert_eq!(
+            Session::format_redirect_hop(1, &hop),
+            "hop 1: 301 https://example.com/ time=5.000 ms [https->http downgrade]"
+        );
+    }
+
+    #[test]
+    fn test_captured_header_summary() {
+        let mut stats =
+            models::PingStats::new("test".to_string(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        for (seq, cache) in [Some("HIT"), Some("MISS"), Some("HIT"), None]
+            .iter()
+            .enumerate()
+        {
+            let detail = models::HttpDetail {
+                headers: vec![("x-cache".to_string(), cache.map(str::to_string))],
+                ..Default::default()
+            };
+            stats.update(&models::PingResult {
+                target: "test".to_string(),
+                target_addr: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                seq: seq as u64,
+                bytes: 0,
+                ttl: None,
+                rtt: Duration::from_millis(10),
+                status: models::ProbeStatus::Success,
+                detail: Some(models::ProbeDetail::Http(detail)),
+            });
+        }
+
+        let header = &stats.captured_headers[0];
+        assert_eq!(header.total(), 4);
+        assert_eq!(
+            Session::format_value_summary(header),
+            "x-cache: HIT 50% (2), MISS 25% (1), - 25% (1)"
+        );
+
+        let json = JsonValueCounts::from_stats(&stats.captured_headers).unwrap();
+        assert_eq!(json[0].values[0].value.as_deref(), Some("HIT"));
+        assert_eq!(json[0].values[0].percent, 50.0);
+        assert!(json[0].values[2].value.is_none());
+    }
+
+    #[test]
+    fn test_format_captured_headers() {
+        assert_eq!(Session::format_captured_headers(&[]), "");
+        let headers = vec![
+            ("x-cache".to_string(), Some("HIT".to_string())),
+            ("via".to_string(), Some("1.1 edge".to_string())),
+            ("cf-ray".to_string(), None),
+        ];
+        assert_eq!(
+            Session::format_captured_headers(&headers),
+            " [x-cache=HIT via=\"1.1 edge\" cf-ray=-]"
+        );
+    }
+
+    #[test]
+    fn test_download_summary() {
+        let download = models::Download {
+            bytes: 1_000_000,
+            transfer: Duration::from_millis(100),
+        };
+        assert_eq!(download.mbps(), Some(80.0));
+        assert_eq!(
+            Session::format_download(&download),
+            " download=1000000 B 80.000 Mbit/s"
+        );
+        let empty = models::Download {
+            bytes: 0,
+            transfer: Duration::ZERO,
+        };
+        assert_eq!(empty.mbps(), None);
+
+        let summary = JsonDownload::from_downloads(&[download, empty]).unwrap();
+        assert_eq!(summary.bytes.avg, 500_000.0);
+        assert_eq!(summary.mbps.unwrap().max, 80.0);
+        assert!(JsonDownload::from_downloads(&[]).is_none());
+    }
+
+    #[test]
+    fn test_neighbor_conflict_stats() {
+        let mut stats =
+            models::PingStats::new("test".to_string(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+        let details = [
+            ([0x02, 0, 0, 0, 0, 0x01], vec![], vec![]),
+            (
+                [0x02, 0, 0, 0, 0, 0x01],
+                vec![[0x02, 0, 0, 0, 0, 0x02]],
+                vec![],
+            ),
+            (
+                [0x02, 0, 0, 0, 0, 0x02],
+                vec![],
+                vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3))],
+            ),
+        ];
+        for (seq, (mac, duplicates, shared_with)) in details.into_iter().enumerate() {
+            stats.update(&result(
+                seq as u64,
+                models::ProbeStatus::Success,
+                models::ProbeDetail::Neighbor(models::NeighborDetail {
+                    mac,
+                    duplicates,
+                    shared_with,
+                }),
+            ));
+        }
+
+        assert_eq!((stats.duplicate_addresses, stats.duplicate_macs), (1, 1));
+        assert_eq!(
+            Session::format_value_summary(&stats.neighbor_macs),
+            "mac: 02:00:00:00:00:01 67% (2), 02:00:00:00:00:02 33% (1)"
+        );
+        assert_eq!(
+            Session::format_neighbor_detail(&models::NeighborDetail {
+                mac: [0x02, 0, 0, 0, 0, 0x01],
+                duplicates: vec![[0x02, 0, 0, 0, 0, 0x02]],
+                shared_with: vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3))],
+            }),
+            " mac=02:00:00:00:00:01 [DUP! also 02:00:00:00:00:02] \
+             [duplicate MAC, also answers for 10.0.0.3]"
+        );
+    }
+
+    #[test]
+    fn test_stun_mapping_stats() {
+        let mut stats =
+            models::PingStats::new("test".to_string(), IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        let a: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let b: SocketAddr = "203.0.113.5:40001".parse().unwrap();
+        for (seq, (mapped, previous)) in
+            [(a, None), (a, None), (b, Some(a))].into_iter().enumerate()
+        {
+            stats.update(&result(
+                seq as u64,
+                models::ProbeStatus::Success,
+                models::ProbeDetail::Stun(models::StunDetail { mapped, previous }),
+            ));
+        }
+
+        assert_eq!(stats.mapping_changes, 1);
+        assert_eq!(
+            Session::format_value_summary(&stats.stun_mappings),
+            "mapping: 203.0.113.5:40000 67% (2), 203.0.113.5:40001 33% (1)"
+        );
+        assert_eq!(
+            Session::format_stun_detail(&models::StunDetail {
+                mapped: b,
+                previous: Some(a),
+            }),
+            " mapped=203.0.113.5:40001 [mapping changed from 203.0.113.5:40000]"
+        );
+        let json = JsonStun::from_stats(&stats).unwrap();
+        assert_eq!((json.mappings.len(), json.changes), (2, 1));
+    }
+
+    #[test]
+    fn test_address_members() {
+        assert_eq!(Session::member_label("db:5432", "db"), "port 5432");
+        assert_eq!(Session::member_label("db@10.0.0.2", "db"), "10.0.0.2");
+        assert_eq!(
+            Session::member_label("db:5432@2001:db8::2", "db"),
+            "2001:db8::2 port 5432"
+        );
+        // Only an address after `@` marks a member of --all-addrs
+        assert_eq!(
+            Session::split_member_addr("http://user@example.com/", "http://user@example.com/"),
+            ("http://user@example.com/", None)
+        );
+
+        let mut all_stats = HashMap::new();
+        for (member, rtts) in [("db@10.0.0.1", vec![2, 4]), ("db@10.0.0.2", vec![9])] {
+            let mut stats =
+                models::PingStats::new(member.to_string(), IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+            stats.transmitted = 2;
+            stats.received = rtts.len() as u64;
+            stats.rtts = rtts.into_iter().map(Duration::from_millis).collect();
+            all_stats.insert(member.to_string(), stats);
+        }
+        let members = ["db@10.0.0.1".to_string(), "db@10.0.0.2".to_string()];
+        let rollups = Session::address_rollups("db", &members, &all_stats);
+        assert_eq!(rollups.len(), 1);
+        let (label, combined) = &rollups[0];
+        assert_eq!(label, "all addresses");
+        assert_eq!((combined.transmitted, combined.received), (4, 3));
+        assert_eq!(combined.rtts.len(), 3);
+    }
+
+    #[test]
+    fn test_dns_rcode_stats() {
+        let mut stats =
+            models::PingStats::new("test".to_string(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        for (seq, (rcode, truncated)) in
+            [("NOERROR", true), ("SERVFAIL", false), ("NOERROR", false)]
+                .into_iter()
+                .enumerate()
+        {
+            let status = match rcode {
+                "NOERROR" => models::ProbeStatus::Success,
+                _ => models::ProbeStatus::Unhealthy(rcode.to_string()),
+            };
+            stats.update(&result(
+                seq as u64,
+                status,
+                models::ProbeDetail::Dns(models::DnsDetail {
+                    rcode,
+                    answers: 1,
+                    truncated,
+                }),
+            ));
+        }
+
+        assert_eq!(
+            (stats.received, stats.unhealthy, stats.dns_truncated),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            Session::format_value_summary(&stats.dns_rcodes),
+            "rcode: NOERROR 67% (2), SERVFAIL 33% (1)"
+        );
+        assert_eq!(
+            Session::format_dns_detail(&models::DnsDetail {
+                rcode: "NOERROR",
+                answers: 2,
+                truncated: true,
+            }),
+            " NOERROR answers=2 [truncated, retried over TCP]"
+        );
+    }
+
+    #[test]
+    fn test_ntp_offset_summary() {
+        let samples: Vec<models::NtpDetail> = [0.002, -0.001, 0.002]
+            .into_iter()
+            .map(|offset| models::NtpDetail {
+                delay: Duration::from_millis(4),
+                offset,
+                stratum: 2,
+                refid: "192.0.2.1".to_string(),
+                leap: "none",
+            })
+            .collect();
+
+        assert_eq!(
+            Session::format_ntp_detail(&samples[1]),
+            " offset=-1.000 ms delay=4.000 ms stratum=2 refid=192.0.2.1 leap=none"
+        );
+        assert_eq!(
+            Session::format_ntp_summary(&samples).unwrap(),
+            "offset: min -1.000, avg +1.000, max +2.000, stdev 1.414 ms"
+        );
+        assert!(Session::format_ntp_summary(&[]).is_none());
+
+        let json = JsonNtp::from_samples(&samples).unwrap();
+        assert_eq!((json.offset.min, json.offset.max), (-1.0, 2.0));
+    }
+
+    #[test]
+    fn test_reflector_directional_loss() {
+        let mut stats =
+            models::PingStats::new("test".to_string(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        // Probe 2 never reached the reflector, and the reply to probe 4 was lost
+        for (seq, count) in [(1, 1), (3, 2), (5, 4)] {
+            stats.update(&result(
+                seq,
+                models::ProbeStatus::Success,
+                models::ProbeDetail::Reflector(models::ReflectorDetail {
+                    forward: 0.004,
+                    reverse: 0.006,
+                    processing: Duration::from_micros(20),
+                    count,
+                }),
+            ));
+        }
+
+        assert_eq!(
+            stats.directional_loss(),
+            Some(models::DirectionalLoss {
+                forward_sent: 5,
+                forward_lost: 1,
+                reverse_sent: 4,
+                reverse_lost: 1,
+            })
+        );
+        assert_eq!(
+            Session::format_reflector_summary(&stats)[1],
+            "loss: forward 1/5 (20.0%), reverse 1/4 (25.0%)"
+        );
+    }
+
+    #[test]
+    fn test_sweep_split() {
+        let addrs = crate::targets::expand_range("192.0.2.0/29")
+            .unwrap()
+            .unwrap();
+        let members: Vec<String> = addrs.iter().map(IpAddr::to_string).collect();
+        let mut all_stats = HashMap::new();
+        for (i, (member, addr)) in members.iter().zip(&addrs).enumerate() {
+            let mut stats = models::PingStats::new(member.clone(), *addr);
+            // Every other address answers, on its second probe
+            for seq in 1..=SWEEP_COUNT {
+                let status = if i % 2 == 0 && seq == 2 {
+                    models::ProbeStatus::Success
+                } else {
+                    models::ProbeStatus::Timeout
+                };
+                stats.update(&models::PingResult {
+                    target: member.clone(),
+                    target_addr: *addr,
+                    seq,
+                    bytes: 0,
+                    ttl: None,
+                    rtt: Duration::from_millis(1),
+                    status,
+                    detail: None,
+                });
+            }
+            all_stats.insert(member.clone(), stats);
+        }
+        let groups = vec![("192.0.2.0/29".to_string(), members)];
+
+        let (alive, unreachable) = Session::sweep_split(&groups, &all_stats);
+        let names = |targets: &SweepTargets| -> Vec<String> {
+            targets
+                .iter()
+                .map(|(target, _)| target.to_string())
+                .collect()
+        };
+        assert_eq!(names(&alive), ["192.0.2.1", "192.0.2.3", "192.0.2.5"]);
+        assert_eq!(names(&unreachable), ["192.0.2.2", "192.0.2.4", "192.0.2.6"]);
+        assert!(alive.iter().all(|(_, stats)| stats.received == 1));
+    }
+
+    #[test]
+    fn test_rate_slot() {
+        let gap = Duration::from_millis(10);
+        let start = tokio::time::Instant::now();
+        // Too early for the next probe
+        assert_eq!(rate_slot(start + gap, start, gap), None);
+        // On time, or late by less than a gap, the next slot follows this one
+        assert_eq!(rate_slot(start, start, gap), Some(start + gap));
+        assert_eq!(
+            rate_slot(start, start + Duration::from_millis(4), gap),
+            Some(start + gap)
+        );
+        // After idling, probes don't burst to catch up
+        let later = start + Duration::from_secs(1);
+        assert_eq!(rate_slot(start, later, gap), Some(later + gap));
+    }
 }
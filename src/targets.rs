@@ -0,0 +1,472 @@
+use crate::cli::{Cli, Protocol};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Name of the list read from stdin.
+pub const STDIN: &str = "-";
+
+/// Most addresses a network or range target expands into.
+const MAX_RANGE_LEN: u128 = 65536;
+
+/// A target to probe, with the options that apply to it.
+#[derive(Debug, Clone)]
+pub struct TargetSpec {
+    pub target: String,
+    /// Name shown instead of the target.
+    pub label: Option<String>,
+    /// The command line options, overridden by those of the targets file.
+    pub cli: Cli,
+}
+
+/// Targets of a session: those known upfront, and plain-text lists whose
+/// targets are probed as they are read.
+#[derive(Debug)]
+pub struct Targets {
+    pub specs: Vec<TargetSpec>,
+    /// Paths of the lists, `-` standing for stdin.
+    pub lists: Vec<String>,
+}
+
+impl Targets {
+    pub fn reads_stdin(&self) -> bool {
+        self.lists.iter().any(|list| list == STDIN)
+    }
+}
+
+/// Collects the targets of the command line, then those of the `-f` file.
+///
+/// A `-` target reads a list from stdin, and a `-f` file other than a
+/// `.toml` one is a list too.
+pub fn from_cli(cli: &Cli) -> Result<Targets> {
+    let mut specs = Vec::new();
+    let mut lists = Vec::new();
+    for target in &cli.targets {
+        if target == STDIN {
+            lists.push(target.clone());
+        } else {
+            specs.push(TargetSpec {
+                target: target.clone(),
+                label: None,
+                cli: cli.clone(),
+            });
+        }
+    }
+    if let Some(path) = &cli.file {
+        let toml = std::path::Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        if toml {
+            let content = std::fs::read_to_string(path)
+                .context(format!("Failed to read targets file: {}", path))?;
+            specs.extend(parse(&content, cli).context(format!("Invalid targets file: {}", path))?);
+        } else if path == STDIN {
+            lists.push(path.clone());
+        } else {
+            // Opened now, so that a missing file fails before probing starts
+            std::fs::File::open(path).context(format!("Failed to read targets file: {}", path))?;
+            lists.push(path.clone());
+        }
+    }
+    if lists.iter().filter(|list| *list == STDIN).count() > 1 {
+        return Err(anyhow!("stdin can only be read once"));
+    }
+    Ok(Targets { specs, lists })
+}
+
+/// Target of a line in a list: one target per line, blank lines and `#`
+/// comments ignored.
+fn parse_line(line: &str) -> Result<Option<&str>> {
+    let mut words = line.split_whitespace();
+    let Some(target) = words.next().filter(|word| !word.starts_with('#')) else {
+        return Ok(None);
+    };
+    match words.next() {
+        Some(word) if !word.starts_with('#') => Err(anyhow!("expected one target per line")),
+        _ => Ok(Some(target)),
+    }
+}
+
+/// Reads the lists in order on a thread of their own, sending each target
+/// as soon as its line is read. The channel closes at the end of the last
+/// list.
+///
+/// A blocking thread does not hold up the exit of the process on a stdin
+/// that never closes, unlike a task of the runtime.
+pub fn read_lists(lists: Vec<String>, cli: Cli) -> mpsc::Receiver<TargetSpec> {
+    let (tx, rx) = mpsc::channel(64);
+    std::thread::spawn(move || {
+        for list in lists {
+            let reader: Box<dyn BufRead> = if list == STDIN {
+                Box::new(std::io::stdin().lock())
+            } else {
+                match std::fs::File::open(&list) {
+                    Ok(file) => Box::new(std::io::BufReader::new(file)),
+                    Err(e) => {
+                        eprintln!("pingx: {}: {}", list, e);
+                        continue;
+                    }
+                }
+            };
+            for (number, line) in reader.lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        eprintln!("pingx: {}: {}", list, e);
+                        break;
+                    }
+                };
+                let target = match parse_line(&line) {
+                    Ok(Some(target)) => target,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("pingx: {}:{}: {}", list, number + 1, e);
+                        continue;
+                    }
+                };
+                let spec = TargetSpec {
+                    target: target.to_string(),
+                    label: None,
+                    cli: cli.clone(),
+                };
+                if tx.blocking_send(spec).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Addresses of a network like `10.0.0.0/24` or a range like
+/// `10.0.0.1-10.0.0.50`, or `None` for any other target.
+///
+/// Like fping, IPv4 networks leave out their network and broadcast
+/// addresses, except for /31 and /32 which have no others.
+pub fn expand_range(target: &str) -> Option<Result<Vec<IpAddr>>> {
+    if let Some((addr, prefix)) = target.split_once('/') {
+        let addr: IpAddr = addr.parse().ok()?;
+        let prefix: u32 = prefix.parse().ok()?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > bits {
+            return Some(Err(anyhow!("Invalid prefix length /{}", prefix)));
+        }
+        let host_bits = bits - prefix;
+        let first = to_bits(addr) & !(u128::MAX.checked_shr(128 - host_bits).unwrap_or(0));
+        let len = 1u128.checked_shl(host_bits).unwrap_or(u128::MAX);
+        let result = if addr.is_ipv4() && host_bits >= 2 {
+            addresses(addr, first + 1, len - 2)
+        } else {
+            addresses(addr, first, len)
+        };
+        return Some(result);
+    }
+
+    let (first, last) = target.split_once('-')?;
+    let (first, last): (IpAddr, IpAddr) = (first.parse().ok()?, last.parse().ok()?);
+    if first.is_ipv4() != last.is_ipv4() {
+        return Some(Err(anyhow!(
+            "A range needs two addresses of the same family"
+        )));
+    }
+    let (start, end) = (to_bits(first), to_bits(last));
+    if end < start {
+        return Some(Err(anyhow!("A range must not end before it starts")));
+    }
+    Some(addresses(first, start, (end - start).saturating_add(1)))
+}
+
+fn to_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr).into(),
+        IpAddr::V6(addr) => addr.into(),
+    }
+}
+
+/// `len` consecutive addresses from `first`, of the family of `family`.
+fn addresses(family: IpAddr, first: u128, len: u128) -> Result<Vec<IpAddr>> {
+    if len > MAX_RANGE_LEN {
+        return Err(anyhow!(
+            "Too many addresses ({}), at most {} can be probed",
+            len,
+            MAX_RANGE_LEN
+        ));
+    }
+    Ok((first..first + len)
+        .map(|bits| match family {
+            IpAddr::V4(_) => Ipv4Addr::from(bits as u32).into(),
+            IpAddr::V6(_) => Ipv6Addr::from(bits).into(),
+        })
+        .collect())
+}
+
+/// Layout of a targets file: a list of `[[targets]]` tables.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetsFile {
+    #[serde(default)]
+    targets: Vec<TargetEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetEntry {
+    target: String,
+    label: Option<String>,
+    /// Forces a protocol like the mode flags. URL targets pick theirs from
+    /// the scheme.
+    protocol: Option<String>,
+    /// Seconds between probes.
+    interval: Option<f64>,
+    count: Option<u64>,
+    /// Seconds to wait for each response.
+    timeout: Option<f64>,
+    size: Option<usize>,
+    /// HTTP headers by name, replacing those of `--header`.
+    headers: Option<BTreeMap<String, String>>,
+    method: Option<String>,
+}
+
+fn parse(content: &str, cli: &Cli) -> Result<Vec<TargetSpec>> {
+    let file: TargetsFile = toml::from_str(content)?;
+    file.targets
+        .into_iter()
+        .map(|entry| {
+            let target = entry.target.clone();
+            entry
+                .apply(cli)
+                .context(format!("Invalid options for target '{}'", target))
+        })
+        .collect()
+}
+
+fn seconds(name: &str, value: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(value)
+        .ok()
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| anyhow!("{} must be a positive number of seconds", name))
+}
+
+impl TargetEntry {
+    fn apply(self, cli: &Cli) -> Result<TargetSpec> {
+        let mut cli = cli.clone();
+
+        if let Some(protocol) = &self.protocol {
+            // The protocol of the entry replaces the mode of the command line
+            cli.ipv4 = false;
+            cli.ipv6 = false;
+            cli.tcp = false;
+            cli.http = false;
+            cli.arp = false;
+            cli.ndp = false;
+            match protocol.to_ascii_lowercase().as_str() {
+                "icmp" => {}
+                "icmp4" => cli.ipv4 = true,
+                "icmp6" => cli.ipv6 = true,
+                "tcp" => cli.tcp = true,
+                "http" => cli.http = true,
+                "arp" => cli.arp = true,
+                "ndp" => cli.ndp = true,
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown protocol '{}', expected icmp, icmp4, icmp6, tcp, http, arp or ndp",
+                        protocol
+                    ));
+                }
+            }
+            if protocol.eq_ignore_ascii_case("icmp")
+                && crate::utils::detect_protocol(&cli, &self.target)?.0 != Protocol::Icmp
+            {
+                return Err(anyhow!("ICMP needs a host name or an IP address"));
+            }
+        }
+        if let Some(interval) = self.interval {
+            cli.interval = seconds("interval", interval)?;
+        }
+        if let Some(timeout) = self.timeout {
+            cli.timeout = seconds("timeout", timeout)?;
+        }
+        if let Some(count) = self.count {
+            cli.count = Some(count);
+        }
+        if let Some(size) = self.size {
+            cli.size = size;
+        }
+        if let Some(headers) = self.headers {
+            cli.headers = headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+        }
+        if let Some(method) = self.method {
+            let parsed = crate::utils::parse_http_method(Some(&method), false, false)?;
+            let assertions = crate::pinger::http::HttpAssertions::from_cli(&cli)?;
+            crate::utils::check_http_method(&parsed, cli.download, &assertions)?;
+            cli.method = Some(method);
+        }
+
+        Ok(TargetSpec {
+            target: self.target,
+            label: self.label,
+            cli,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_parse_targets_file() {
+        let cli = Cli::parse_from(["pingx", "-c", "3", "--header", "X-Env: prod", "10.0.0.1"]);
+        let specs = parse(
+            r#"
+            [[targets]]
+            target = "10.0.0.254"
+            label = "gateway"
+
+            [[targets]]
+            target = "db.internal:5432"
+            interval = 5
+            timeout = 2.5
+
+            [[targets]]
+            target = "api.internal"
+            protocol = "http"
+            count = 10
+            method = "get"
+            headers = { Authorization = "Bearer token" }
+            "#,
+            &cli,
+        )
+        .unwrap();
+
+        assert_eq!(specs.len(), 3);
+        assert_eq!(specs[0].label.as_deref(), Some("gateway"));
+        assert_eq!(specs[0].cli.count, Some(3));
+        assert_eq!(specs[0].cli.headers, ["X-Env: prod"]);
+        assert_eq!(specs[1].cli.interval, Duration::from_secs(5));
+        assert_eq!(specs[1].cli.timeout, Duration::from_millis(2500));
+        assert!(specs[2].cli.http);
+        assert_eq!(specs[2].cli.count, Some(10));
+        assert_eq!(specs[2].cli.method.as_deref(), Some("get"));
+        assert_eq!(specs[2].cli.headers, ["Authorization: Bearer token"]);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let cli = Cli::parse_from(["pingx", "-T", "10.0.0.1:22"]);
+        let entry = |toml: &str| parse(&format!("[[targets]]\n{}", toml), &cli);
+
+        assert!(entry("target = \"a\"\nprotocol = \"sctp\"").is_err());
+        assert!(entry("target = \"a\"\ninterval = 0").is_err());
+        assert!(entry("target = \"a\"\ntimeout = -1").is_err());
+        assert!(entry("target = \"a\"\nttl = 3").is_err());
+        assert!(entry("target = \"a:80\"\nprotocol = \"icmp\"").is_err());
+
+        // An entry protocol replaces the mode flags of the command line
+        let spec = &entry("target = \"10.0.0.2\"\nprotocol = \"icmp\"").unwrap()[0];
+        assert!(!spec.cli.tcp);
+
+        // HEAD gets no body for the body checks of the command line
+        let cli = Cli::parse_from(["pingx", "--expect-body", "ok", "http://a/"]);
+        let head = "[[targets]]\ntarget = \"http://b/\"\nmethod = \"head\"";
+        assert!(parse(head, &cli).is_err());
+        let cli = Cli::parse_from(["pingx", "--download", "http://a/"]);
+        assert!(parse(head, &cli).is_err());
+        let cli = Cli::parse_from(["pingx", "http://a/"]);
+        assert!(parse(head, &cli).is_ok());
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("10.0.0.1").unwrap(), Some("10.0.0.1"));
+        assert_eq!(
+            parse_line("  db.internal:5432\r").unwrap(),
+            Some("db.internal:5432")
+        );
+        assert_eq!(parse_line("10.0.0.2  # gateway").unwrap(), Some("10.0.0.2"));
+        assert_eq!(
+            parse_line("https://example.com/#top").unwrap(),
+            Some("https://example.com/#top")
+        );
+        assert_eq!(parse_line("").unwrap(), None);
+        assert_eq!(parse_line("   ").unwrap(), None);
+        assert_eq!(parse_line("# 10.0.0.3").unwrap(), None);
+        assert!(parse_line("10.0.0.1 10.0.0.2").is_err());
+    }
+
+    #[test]
+    fn test_lists() {
+        // A missing list fails before probing starts
+        let cli = Cli::parse_from(["pingx", "-f", "/nonexistent/hosts.txt"]);
+        assert!(from_cli(&cli).is_err());
+
+        let cli = Cli::parse_from(["pingx", "10.0.0.1", "-"]);
+        let targets = from_cli(&cli).unwrap();
+        assert_eq!(targets.specs.len(), 1);
+        assert_eq!(targets.lists, ["-"]);
+        assert!(targets.reads_stdin());
+
+        let cli = Cli::parse_from(["pingx", "-", "-f", "-"]);
+        assert!(from_cli(&cli).is_err());
+    }
+
+    #[test]
+    fn test_expand_range() {
+        let expand = |target: &str| expand_range(target).map(|addrs| addrs.unwrap());
+        let ip = |addr: &str| addr.parse::<IpAddr>().unwrap();
+
+        let network = expand("10.0.0.77/24").unwrap();
+        assert_eq!(network.len(), 254);
+        assert_eq!(network[0], ip("10.0.0.1"));
+        assert_eq!(network[253], ip("10.0.0.254"));
+        assert_eq!(
+            expand("10.0.0.6/31").unwrap(),
+            [ip("10.0.0.6"), ip("10.0.0.7")]
+        );
+        assert_eq!(expand("10.0.0.6/32").unwrap(), [ip("10.0.0.6")]);
+        assert_eq!(expand("2001:db8::/126").unwrap().len(), 4);
+
+        let range = expand("10.0.0.250-10.0.1.4").unwrap();
+        assert_eq!(range.len(), 11);
+        assert_eq!(range[10], ip("10.0.1.4"));
+        assert_eq!(expand("fe80::1-fe80::3").unwrap().len(), 3);
+
+        // Other targets are left alone
+        assert!(expand("example.com").is_none());
+        assert!(expand("my-host.example.com").is_none());
+        assert!(expand("https://10.0.0.1/health").is_none());
+        assert!(expand("10.0.0.1:22").is_none());
+
+        let invalid = |target: &str| expand_range(target).unwrap().is_err();
+        assert!(invalid("10.0.0.0/33"));
+        assert!(invalid("10.0.0.0/8"));
+        assert!(invalid("2001:db8::/64"));
+        assert!(invalid("::/0"));
+        assert!(invalid("10.0.0.9-10.0.0.1"));
+        assert!(invalid("10.0.0.1-::1"));
+    }
+
+    #[tokio::test]
+    async fn test_read_lists() {
+        let path = std::env::temp_dir().join(format!("pingx-hosts-{}.txt", std::process::id()));
+        std::fs::write(&path, "# inventory\n10.0.0.1\n\n10.0.0.2:22 # ssh\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let cli = Cli::parse_from(["pingx", "-f", &path]);
+        let targets = from_cli(&cli).unwrap();
+        assert!(targets.specs.is_empty());
+        let mut rx = read_lists(targets.lists, cli);
+        assert_eq!(rx.recv().await.unwrap().target, "10.0.0.1");
+        assert_eq!(rx.recv().await.unwrap().target, "10.0.0.2:22");
+        assert!(rx.recv().await.is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
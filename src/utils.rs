@@ -4,7 +4,7 @@ use colored::Colorize;
 use std::net::IpAddr;
 use tokio::net::lookup_host;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum IpVersion {
     V4,
     V6,
@@ -50,32 +50,46 @@ pub async fn resolve_host(host: &str, version: IpVersion) -> Result<Vec<IpAddr>>
 }
 
 #[cfg(target_os = "linux")]
-pub async fn check_and_acquire_privileges(cli: &crate::cli::Cli) -> Result<()> {
+pub async fn check_and_acquire_privileges(
+    cli: &crate::cli::Cli,
+    all_targets: &crate::targets::Targets,
+) -> Result<()> {
+    let targets = &all_targets.specs;
     // Check if we need raw socket privileges by inspecting all targets
     // If we have explicit ICMP flags, we definitely need raw socket.
-    let needs_raw_socket = if cli.ipv4 || cli.ipv6 {
-        true
-    } else {
-        // Iterate over targets to check if any requires ICMP
-        let mut has_icmp = false;
-        for target in &cli.targets {
-            match detect_protocol(cli, target) {
-                Ok((protocol, _)) => {
-                    if let crate::cli::Protocol::Icmp = protocol {
+    // ARP and NDP probes are sent on packet sockets, which need the same capability,
+    // whether the targets are given here or come from lists
+    let needs_packet_socket =
+        cli.arp || cli.ndp || targets.iter().any(|spec| spec.cli.arp || spec.cli.ndp);
+    let needs_raw_socket =
+        if needs_packet_socket || targets.iter().any(|spec| spec.cli.ipv4 || spec.cli.ipv6) {
+            true
+        } else {
+            // Targets of lists are only known once read, and may be hosts for ICMP
+            // unless the command line forces another mode
+            let mut has_icmp = !(all_targets.lists.is_empty() || cli.tcp || cli.http);
+            // Iterate over targets to check if any requires ICMP
+            for spec in targets {
+                match expand_target(&spec.cli, &spec.target) {
+                    Ok(specs) => {
+                        if specs
+                            .iter()
+                            .any(|(_, protocol, _)| *protocol == crate::cli::Protocol::Icmp)
+                        {
+                            has_icmp = true;
+                            break;
+                        }
+                    }
+                    Err(_) => {
+                        // If detection fails, we might default to ICMP or error out later.
+                        // Assuming safe default: if we can't parse it as TCP/HTTP, it might be a hostname for ICMP.
                         has_icmp = true;
                         break;
                     }
                 }
-                Err(_) => {
-                    // If detection fails, we might default to ICMP or error out later.
-                    // Assuming safe default: if we can't parse it as TCP/HTTP, it might be a hostname for ICMP.
-                    has_icmp = true;
-                    break;
-                }
             }
-        }
-        has_icmp
-    };
+            has_icmp
+        };
 
     if !needs_raw_socket {
         return Ok(());
@@ -104,13 +118,20 @@ pub async fn check_and_acquire_privileges(cli: &crate::cli::Cli) -> Result<()> {
     // If DGRAM works, we don't need to prompt.
     // If DGRAM fails and RAW works, we don't need to prompt.
     // If both fail, and RAW failed with PermissionDenied, we prompt.
+    // Packet sockets for ARP and NDP have no unprivileged variant.
 
-    let can_create_dgram = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)).is_ok();
+    let can_create_dgram = !needs_packet_socket
+        && Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)).is_ok();
     if can_create_dgram {
         return Ok(());
     }
 
-    match Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)) {
+    let privileged = if needs_packet_socket {
+        Socket::new(Domain::PACKET, Type::DGRAM, None)
+    } else {
+        Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))
+    };
+    match privileged {
         Ok(_) => return Ok(()),
         Err(e) => {
             // If it's not a permission error, return it
@@ -121,6 +142,24 @@ pub async fn check_and_acquire_privileges(cli: &crate::cli::Cli) -> Result<()> {
     }
 
     let is_zh = is_chinese_locale();
+    let current_exe = std::env::current_exe()?;
+    let exe_path = current_exe.to_string_lossy();
+
+    // The answer to the prompt would be taken from the targets
+    if all_targets.reads_stdin() {
+        let msg = if is_zh {
+            format!(
+                "从 stdin 读取目标时无法询问授权，请先运行: sudo setcap cap_net_raw+ep {}",
+                exe_path
+            )
+        } else {
+            format!(
+                "cannot prompt for 'cap_net_raw' while reading targets from stdin, run first: sudo setcap cap_net_raw+ep {}",
+                exe_path
+            )
+        };
+        return Err(anyhow::anyhow!("{}", msg));
+    }
 
     if is_zh {
         println!(
@@ -137,9 +176,6 @@ pub async fn check_and_acquire_privileges(cli: &crate::cli::Cli) -> Result<()> {
     }
     println!();
 
-    let current_exe = std::env::current_exe()?;
-    let exe_path = current_exe.to_string_lossy();
-
     println!(
         "{}",
         format!("  sudo setcap cap_net_raw+ep {}", exe_path).yellow()
@@ -198,10 +234,29 @@ pub async fn check_and_acquire_privileges(cli: &crate::cli::Cli) -> Result<()> {
 }
 
 #[cfg(not(target_os = "linux"))]
-pub async fn check_and_acquire_privileges(_cli: &crate::cli::Cli) -> Result<()> {
+pub async fn check_and_acquire_privileges(
+    _cli: &crate::cli::Cli,
+    _targets: &crate::targets::Targets,
+) -> Result<()> {
     Ok(())
 }
 
+/// Host and port of a URL target such as `ntp://[::1]:1123`, without the
+/// brackets of an IPv6 host, and `default_port` when the URL has no port.
+/// `kind` names the target in errors, e.g. `NTP target`.
+pub fn url_host_port(target: &str, kind: &str, default_port: u16) -> Result<(String, u16)> {
+    let url = reqwest::Url::parse(target)
+        .map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", kind, target, e))?;
+    let host = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid {} '{}': missing host", kind, target))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    Ok((host, url.port().unwrap_or(default_port)))
+}
+
 pub fn detect_protocol(
     cli: &crate::cli::Cli,
     target: &str,
@@ -210,6 +265,12 @@ pub fn detect_protocol(
     if cli.ipv4 || cli.ipv6 {
         return Ok((crate::cli::Protocol::Icmp, target.to_string()));
     }
+    if cli.arp {
+        return Ok((crate::cli::Protocol::Arp, target.to_string()));
+    }
+    if cli.ndp {
+        return Ok((crate::cli::Protocol::Ndp, target.to_string()));
+    }
     if cli.tcp {
         if let Some((host, port_str)) = target.rsplit_once(':') {
             let host = if host.starts_with('[') && host.ends_with(']') {
@@ -226,6 +287,79 @@ pub fn detect_protocol(
             "TCP mode requires target format <host>:<port>"
         ));
     }
+    if target.starts_with("ws://") || target.starts_with("wss://") {
+        let (host, _) = url_host_port(target, "WebSocket URL", 80)?;
+        return Ok((crate::cli::Protocol::WebSocket(target.to_string()), host));
+    }
+    if target.starts_with("grpc://") || target.starts_with("grpcs://") {
+        let (host, _) = url_host_port(target, "gRPC URL", 80)?;
+        return Ok((crate::cli::Protocol::Grpc(target.to_string()), host));
+    }
+    if let Some(service) = target
+        .split_once("://")
+        .and_then(|(scheme, _)| crate::pinger::service::Service::from_scheme(scheme))
+    {
+        let (host, _) = url_host_port(
+            target,
+            &format!("{} URL", service.name()),
+            service.default_port(),
+        )?;
+        let target = target.to_string();
+        let protocol = match service {
+            crate::pinger::service::Service::Redis => crate::cli::Protocol::Redis(target),
+            crate::pinger::service::Service::Memcached => crate::cli::Protocol::Memcached(target),
+            crate::pinger::service::Service::Postgres => crate::cli::Protocol::Postgres(target),
+        };
+        return Ok((protocol, host));
+    }
+    if target.starts_with("udp://") {
+        // UDP has no default port, and port 0 can't be probed
+        return match url_host_port(target, "UDP target", 0) {
+            Ok((host, port)) if port != 0 => Ok((crate::cli::Protocol::Udp(port), host)),
+            _ => Err(anyhow::anyhow!(
+                "UDP mode requires target format udp://<host>:<port>"
+            )),
+        };
+    }
+    if target.starts_with("pingx://") {
+        let (host, port) =
+            url_host_port(target, "reflector target", crate::reflector::DEFAULT_PORT)?;
+        return Ok((crate::cli::Protocol::Reflector(port), host));
+    }
+    if target.starts_with("ntp://") {
+        let (host, port) = url_host_port(target, "NTP target", crate::pinger::ntp::DEFAULT_PORT)?;
+        return Ok((crate::cli::Protocol::Ntp(port), host));
+    }
+    if target.starts_with("stun://") {
+        let (host, port) = url_host_port(target, "STUN target", crate::pinger::stun::DEFAULT_PORT)?;
+        return Ok((crate::cli::Protocol::Stun(port), host));
+    }
+    if let Some(path) = target.strip_prefix("unix://") {
+        if !path.starts_with('/') {
+            return Err(anyhow::anyhow!(
+                "Invalid Unix socket target '{}': expected an absolute path, e.g. unix:///run/app.sock",
+                target
+            ));
+        }
+        return Ok((
+            crate::cli::Protocol::Unix(path.to_string()),
+            path.to_string(),
+        ));
+    }
+    if target.starts_with("dns://") {
+        let (host, _) = url_host_port(target, "DNS URL", crate::pinger::dns::DEFAULT_PORT)?;
+        return Ok((crate::cli::Protocol::Dns(target.to_string()), host));
+    }
+    if target.starts_with("http+unix://") {
+        let url = reqwest::Url::parse(target)
+            .map_err(|e| anyhow::anyhow!("Invalid HTTP URL '{}': {}", target, e))?;
+        let path = crate::pinger::unix::http_socket_path(&url)
+            .ok_or_else(|| anyhow::anyhow!("Invalid HTTP URL '{}': missing socket path", target))?;
+        return Ok((
+            crate::cli::Protocol::Http(target.to_string()),
+            path.display().to_string(),
+        ));
+    }
     if cli.http {
         let url_str = if target.starts_with("http") {
             target.to_string()
@@ -288,6 +422,150 @@ pub fn detect_protocol(
     Ok((crate::cli::Protocol::Icmp, target.to_string()))
 }
 
+/// Expands a target into one or more probe entries of `(name, protocol, host)`.
+///
+/// A TCP target may carry a port list (`host:22,80,443`) or a port range
+/// (`host:8000-8010`), in which case one entry per port is produced and each
+/// entry is named `host:port`. Any other target is passed to `detect_protocol`.
+pub fn expand_target(
+    cli: &crate::cli::Cli,
+    target: &str,
+) -> Result<Vec<(String, crate::cli::Protocol, String)>> {
+    if !(cli.ipv4 || cli.ipv6 || cli.arp || cli.ndp || cli.http || target.contains("://"))
+        && let Some((host, spec)) = target.rsplit_once(':')
+        && (spec.contains(',') || spec.contains('-'))
+    {
+        let ports = parse_port_spec(spec)?;
+        let clean_host = if host.starts_with('[') && host.ends_with(']') {
+            &host[1..host.len() - 1]
+        } else {
+            host
+        };
+
+        return Ok(ports
+            .into_iter()
+            .map(|port| {
+                (
+                    format!("{}:{}", host, port),
+                    crate::cli::Protocol::Tcp(port),
+                    clean_host.to_string(),
+                )
+            })
+            .collect());
+    }
+
+    let (protocol, host) = detect_protocol(cli, target)?;
+    Ok(vec![(target.to_string(), protocol, host)])
+}
+
+/// Most ports a port specification may expand to, each getting a pinger.
+const MAX_PORTS: usize = 1024;
+
+/// Parses a port specification such as `22,80,443`, `8000-8010` or a mix of both.
+pub fn parse_port_spec(spec: &str) -> Result<Vec<u16>> {
+    let mut ports = Vec::new();
+
+    for part in spec.split(',') {
+        let part = part.trim();
+        if let Some((start, end)) = part.split_once('-') {
+            let start: u16 = start
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid port range: '{}'", part))?;
+            let end: u16 = end
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid port range: '{}'", part))?;
+            if start > end {
+                return Err(anyhow::anyhow!("Invalid port range: '{}'", part));
+            }
+            ports.extend(start..=end);
+        } else {
+            let port: u16 = part
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid port: '{}'", part))?;
+            ports.push(port);
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    ports.retain(|port| seen.insert(*port));
+
+    if ports.is_empty() || ports.contains(&0) {
+        return Err(anyhow::anyhow!("Invalid port specification: '{}'", spec));
+    }
+    if ports.len() > MAX_PORTS {
+        return Err(anyhow::anyhow!(
+            "Too many ports ({}), at most {} can be probed",
+            ports.len(),
+            MAX_PORTS
+        ));
+    }
+
+    Ok(ports)
+}
+
+/// Loads the HTTP request body from `--data` or `--data-file`.
+pub fn load_http_body(cli: &crate::cli::Cli) -> Result<bytes::Bytes> {
+    if let Some(data) = &cli.data {
+        return Ok(bytes::Bytes::from(data.clone()));
+    }
+    if let Some(path) = &cli.data_file {
+        let data = std::fs::read(path).context(format!("Failed to read data file: {}", path))?;
+        return Ok(bytes::Bytes::from(data));
+    }
+    Ok(bytes::Bytes::new())
+}
+
+/// Picks the HTTP method: the explicit `--method`, POST when a body is sent,
+/// GET when the response body is inspected, and HEAD otherwise.
+pub fn parse_http_method(
+    method: Option<&str>,
+    has_body: bool,
+    needs_response_body: bool,
+) -> Result<reqwest::Method> {
+    match method {
+        Some(m) => reqwest::Method::from_bytes(m.to_uppercase().as_bytes())
+            .map_err(|_| anyhow::anyhow!("Invalid HTTP method: '{}'", m)),
+        None if has_body => Ok(reqwest::Method::POST),
+        None if needs_response_body => Ok(reqwest::Method::GET),
+        None => Ok(reqwest::Method::HEAD),
+    }
+}
+
+/// Rejects HEAD when the response body is downloaded or checked, as its
+/// responses have none.
+pub fn check_http_method(
+    method: &reqwest::Method,
+    download: bool,
+    assertions: &crate::pinger::http::HttpAssertions,
+) -> Result<()> {
+    if *method == reqwest::Method::HEAD && (download || assertions.inspects_body()) {
+        return Err(anyhow::anyhow!(
+            "HEAD requests have no response body to download or check"
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the names given to `--capture-header`, which may also be comma
+/// separated. Duplicates are dropped, keeping the first position.
+pub fn parse_header_names(raw_names: &[String]) -> Result<Vec<reqwest::header::HeaderName>> {
+    let mut names = Vec::new();
+    for raw in raw_names.iter().flat_map(|raw| raw.split(',')) {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        let name = reqwest::header::HeaderName::from_bytes(raw.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Invalid header name: '{}'", raw))?;
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
 pub fn parse_headers(raw_headers: &[String]) -> Result<reqwest::header::HeaderMap> {
     use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
     let mut headers = HeaderMap::new();
@@ -363,6 +641,13 @@ pub fn parse_headers(raw_headers: &[String]) -> Result<reqwest::header::HeaderMa
 mod tests {
     use super::*;
 
+    fn test_cli() -> crate::cli::Cli {
+        use clap::Parser;
+        let mut cli = crate::cli::Cli::parse_from(["pingx", "localhost"]);
+        cli.targets.clear();
+        cli
+    }
+
     #[test]
     fn test_parse_headers() {
         let raw = vec![
@@ -391,6 +676,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_header_names() {
+        let raw = vec!["X-Cache, age".to_string(), "x-cache".to_string()];
+        let names = parse_header_names(&raw).unwrap();
+        assert_eq!(names, ["x-cache", "age"]);
+
+        assert!(parse_header_names(&["bad name".to_string()]).is_err());
+    }
+
     #[tokio::test]
     async fn test_resolve_host_filtering() {
         // IPv4
@@ -419,7 +713,9 @@ mod tests {
     #[test]
     fn test_detect_protocol() {
         let mut cli = crate::cli::Cli {
+            command: None,
             targets: vec![],
+            file: None,
             count: None,
             interval: std::time::Duration::from_secs(1),
             timeout: std::time::Duration::from_secs(1),
@@ -427,11 +723,40 @@ mod tests {
             ttl: 64,
             size: 56,
             quiet: false,
+            all_addrs: false,
+            re_resolve: None,
+            sweep: false,
+            rate: None,
             ipv4: false,
             ipv6: false,
             tcp: false,
             http: false,
+            arp: false,
+            ndp: false,
+            interface: None,
             headers: vec![],
+            capture_headers: vec![],
+            method: None,
+            data: None,
+            data_file: None,
+            download: false,
+            download_limit: None,
+            http2: false,
+            http3: false,
+            reuse: false,
+            no_reuse: false,
+            follow_redirects: false,
+            max_redirects: 10,
+            proxy: None,
+            insecure: false,
+            cacert: None,
+            cert: None,
+            key: None,
+            expect_status: None,
+            expect_body: None,
+            expect_body_regex: None,
+            min_body_size: None,
+            max_body_size: None,
             geo: false,
             fetch_geo: false,
             json: None,
@@ -467,25 +792,174 @@ mod tests {
         assert!(matches!(proto, crate::cli::Protocol::Http(ref s) if s == "https://google.com"));
         assert_eq!(target, "google.com");
 
-        // 7. Force TCP
+        // 7. Auto WebSocket (ws:// and wss://)
+        let (proto, target) = detect_protocol(&cli, "wss://[::1]:8443/chat").unwrap();
+        assert!(
+            matches!(proto, crate::cli::Protocol::WebSocket(ref s) if s == "wss://[::1]:8443/chat")
+        );
+        assert_eq!(target, "::1");
+
+        // 8. Auto gRPC (grpc:// and grpcs://)
+        let (proto, target) = detect_protocol(&cli, "grpcs://api.example.com/billing").unwrap();
+        assert!(
+            matches!(proto, crate::cli::Protocol::Grpc(ref s) if s == "grpcs://api.example.com/billing")
+        );
+        assert_eq!(target, "api.example.com");
+
+        // 9. Auto DNS (dns://)
+        let (proto, target) =
+            detect_protocol(&cli, "dns://[2606:4700::1111]/example.com?type=AAAA").unwrap();
+        assert!(
+            matches!(proto, crate::cli::Protocol::Dns(ref s) if s == "dns://[2606:4700::1111]/example.com?type=AAAA")
+        );
+        assert_eq!(target, "2606:4700::1111");
+
+        // 10. Auto UDP (udp://)
+        let (proto, target) = detect_protocol(&cli, "udp://[::1]:3478").unwrap();
+        assert_eq!(proto, crate::cli::Protocol::Udp(3478));
+        assert_eq!(target, "::1");
+        assert!(detect_protocol(&cli, "udp://example.com").is_err());
+
+        // 11. Auto NTP and STUN (ntp://, stun://)
+        let (proto, target) = detect_protocol(&cli, "ntp://time.example.com").unwrap();
+        assert_eq!(proto, crate::cli::Protocol::Ntp(123));
+        assert_eq!(target, "time.example.com");
+        let (proto, target) = detect_protocol(&cli, "stun://[2001:db8::3]:19302").unwrap();
+        assert_eq!(proto, crate::cli::Protocol::Stun(19302));
+        assert_eq!(target, "2001:db8::3");
+
+        // 12. Auto reflector (pingx://)
+        let (proto, target) = detect_protocol(&cli, "pingx://10.0.0.9").unwrap();
+        assert_eq!(proto, crate::cli::Protocol::Reflector(8862));
+        assert_eq!(target, "10.0.0.9");
+
+        // 13. Auto services (redis://, memcached://, postgres://)
+        let (proto, target) = detect_protocol(&cli, "redis://:secret@cache:6380").unwrap();
+        assert!(
+            matches!(proto, crate::cli::Protocol::Redis(ref s) if s == "redis://:secret@cache:6380")
+        );
+        assert_eq!(target, "cache");
+        let (proto, _) = detect_protocol(&cli, "memcached://10.0.0.5").unwrap();
+        assert!(matches!(proto, crate::cli::Protocol::Memcached(_)));
+        let (proto, target) = detect_protocol(&cli, "postgresql://app@[::1]/orders").unwrap();
+        assert!(matches!(proto, crate::cli::Protocol::Postgres(_)));
+        assert_eq!(target, "::1");
+
+        // 14. Unix domain sockets (unix://, http+unix://)
+        let (proto, target) = detect_protocol(&cli, "unix:///run/app.sock").unwrap();
+        assert_eq!(
+            proto,
+            crate::cli::Protocol::Unix("/run/app.sock".to_string())
+        );
+        assert_eq!(target, "/run/app.sock");
+        assert!(detect_protocol(&cli, "unix://app.sock").is_err());
+        let (proto, target) =
+            detect_protocol(&cli, "http+unix://%2Fvar%2Frun%2Fdocker.sock/_ping").unwrap();
+        assert!(matches!(proto, crate::cli::Protocol::Http(_)));
+        assert_eq!(target, "/var/run/docker.sock");
+
+        // 15. Force TCP
         cli.tcp = true;
         let (proto, target) = detect_protocol(&cli, "google.com:443").unwrap();
         assert_eq!(proto, crate::cli::Protocol::Tcp(443));
         assert_eq!(target, "google.com");
         cli.tcp = false;
 
-        // 8. Force HTTP
+        // 16. Force HTTP
         cli.http = true;
         let (proto, target) = detect_protocol(&cli, "google.com").unwrap();
         assert!(matches!(proto, crate::cli::Protocol::Http(ref s) if s == "http://google.com"));
         assert_eq!(target, "google.com");
         cli.http = false;
 
-        // 9. Force IPv4 (ICMP)
+        // 17. Force IPv4 (ICMP)
         cli.ipv4 = true;
         let (proto, target) = detect_protocol(&cli, "google.com").unwrap();
         assert_eq!(proto, crate::cli::Protocol::Icmp);
         assert_eq!(target, "google.com");
         cli.ipv4 = false;
+
+        // 18. ARP and NDP
+        cli.arp = true;
+        let (proto, target) = detect_protocol(&cli, "192.168.1.1").unwrap();
+        assert_eq!(proto, crate::cli::Protocol::Arp);
+        assert_eq!(target, "192.168.1.1");
+        cli.arp = false;
+        cli.ndp = true;
+        let (proto, target) = detect_protocol(&cli, "fe80::1").unwrap();
+        assert_eq!(proto, crate::cli::Protocol::Ndp);
+        assert_eq!(target, "fe80::1");
+        cli.ndp = false;
+    }
+
+    #[test]
+    fn test_parse_http_method() {
+        use reqwest::Method;
+        assert_eq!(parse_http_method(None, false, false).unwrap(), Method::HEAD);
+        assert_eq!(parse_http_method(None, false, true).unwrap(), Method::GET);
+        assert_eq!(parse_http_method(None, true, true).unwrap(), Method::POST);
+        assert_eq!(
+            parse_http_method(Some("put"), true, false).unwrap(),
+            Method::PUT
+        );
+        assert!(parse_http_method(Some("BAD METHOD"), false, false).is_err());
+    }
+
+    #[test]
+    fn test_url_host_port() {
+        assert_eq!(
+            url_host_port("ntp://[::1]:1123", "NTP target", 123).unwrap(),
+            ("::1".to_string(), 1123)
+        );
+        assert_eq!(
+            url_host_port("stun://stun.example.com", "STUN target", 3478).unwrap(),
+            ("stun.example.com".to_string(), 3478)
+        );
+        let err = url_host_port("ntp://:123", "NTP target", 123).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid NTP target"));
+    }
+
+    #[test]
+    fn test_parse_port_spec() {
+        assert_eq!(parse_port_spec("22,80,443").unwrap(), vec![22, 80, 443]);
+        assert_eq!(
+            parse_port_spec("8000-8003").unwrap(),
+            vec![8000, 8001, 8002, 8003]
+        );
+        assert_eq!(
+            parse_port_spec("22,8000-8002,22").unwrap(),
+            vec![22, 8000, 8001, 8002]
+        );
+        assert!(parse_port_spec("80-22").is_err());
+        assert!(parse_port_spec("22,abc").is_err());
+        assert!(parse_port_spec("0-2").is_err());
+        assert_eq!(parse_port_spec("1-1024").unwrap().len(), 1024);
+        assert!(parse_port_spec("1-65535").is_err());
+    }
+
+    #[test]
+    fn test_expand_target() {
+        let cli = test_cli();
+
+        let specs = expand_target(&cli, "example.com:22,80").unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].0, "example.com:22");
+        assert_eq!(specs[0].1, crate::cli::Protocol::Tcp(22));
+        assert_eq!(specs[0].2, "example.com");
+        assert_eq!(specs[1].0, "example.com:80");
+
+        let specs = expand_target(&cli, "[::1]:8000-8001").unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[1].0, "[::1]:8001");
+        assert_eq!(specs[1].2, "::1");
+
+        // Plain targets are passed through unchanged
+        let specs = expand_target(&cli, "example.com:443").unwrap();
+        assert_eq!(specs.len(), 1);
+        assert_eq!(specs[0].0, "example.com:443");
+        assert_eq!(specs[0].1, crate::cli::Protocol::Tcp(443));
+
+        let specs = expand_target(&cli, "https://example.com/a-b").unwrap();
+        assert_eq!(specs.len(), 1);
     }
 }